@@ -1,3 +1,4 @@
 pub mod mongodb;
 pub mod reqwest;
 pub mod rusoto;
+pub mod source;
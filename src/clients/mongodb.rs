@@ -4,17 +4,21 @@ use rayon::prelude::*;
 #[derive(Clone)]
 pub struct Client {
     client: mongodb::Client,
+    database: String,
 }
 
 impl Client {
     pub async fn new(config: configs::mongodb::Config) -> Result<Self, Box<dyn std::error::Error>> {
+        // MONGODB_URI points the robot at another deployment, e.g. a local one for tests
+        let uri = config.uri.unwrap_or(format!(
+            "mongodb://{}:{}@ac-mt2requ-shard-00-00.pflxmhx.mongodb.net:27017,ac-mt2requ-shard-00-01.pflxmhx.mongodb.net:27017,ac-mt2requ-shard-00-02.pflxmhx.mongodb.net:27017/?ssl=true&replicaSet=atlas-g6x5st-shard-0&authSource=admin&retryWrites=true&w=majority",
+            config.username, config.password
+        ));
         return Ok(Self {
             client: mongodb::Client::with_options(
-               mongodb::options::ClientOptions::parse(format!(
-                    "mongodb://{}:{}@ac-mt2requ-shard-00-00.pflxmhx.mongodb.net:27017,ac-mt2requ-shard-00-01.pflxmhx.mongodb.net:27017,ac-mt2requ-shard-00-02.pflxmhx.mongodb.net:27017/?ssl=true&replicaSet=atlas-g6x5st-shard-0&authSource=admin&retryWrites=true&w=majority",
-                    config.username, config.password
-                )).await?,
-            )?
+                mongodb::options::ClientOptions::parse(uri).await?,
+            )?,
+            database: config.database,
         });
     }
 
@@ -28,7 +32,7 @@ impl Client {
 
         let insert_one_result = self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<mongodb::bson::Document>("robots")
             .insert_one(mongodb::bson::to_document(&robot.data)?, None)
             .await?;
@@ -44,7 +48,7 @@ impl Client {
     ) -> Result<Option<models::robot::RobotData>, mongodb::error::Error> {
         return Ok(self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<models::robot::RobotData>("robots")
             .find_one(mongodb::bson::to_document(&robot_id)?, None)
             .await?);
@@ -53,7 +57,7 @@ impl Client {
     pub async fn get_robots(&self) -> Result<Vec<models::robot::RobotData>, mongodb::error::Error> {
         return futures::TryStreamExt::try_collect(
             self.client
-                .database("robots")
+                .database(&self.database)
                 .collection::<models::robot::RobotData>("robots")
                 .find(None, None)
                 .await?,
@@ -77,9 +81,11 @@ impl Client {
             self.delete_purge_user(data).await?;
         }
 
+        self.delete_snapshot(robot_id).await?;
+
         return Ok(self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<models::robot::RobotData>("robots")
             .delete_one(mongodb::bson::to_document(&robot_id)?, None)
             .await?);
@@ -91,7 +97,7 @@ impl Client {
     ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
         return self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<mongodb::bson::Document>("robots")
             .update_one(
                 mongodb::bson::doc! {"_id": robot.data.id.unique.unwrap()},
@@ -101,15 +107,95 @@ impl Client {
             .await;
     }
 
+    pub async fn set_robot_status(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
+        // Only the fields the scheduler owns, edits made through the API during a run are kept
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("robots")
+            .update_one(
+                mongodb::bson::doc! {"_id": robot.data.id.unique},
+                mongodb::bson::doc! {"$set": {
+                    "modified": mongodb::bson::to_bson(&robot.data.modified)?,
+                    "breaker": mongodb::bson::to_bson(&robot.data.breaker)?,
+                    "credentialStatus": mongodb::bson::to_bson(&robot.data.credential_status)?,
+                    "lastRun": mongodb::bson::to_bson(&robot.data.last_run)?,
+                }},
+                None,
+            )
+            .await;
+    }
+
+    pub async fn pause_robot(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+        breaker: &models::robot::RobotBreaker,
+    ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("robots")
+            .update_one(
+                mongodb::bson::doc! {"_id": robot_id.unique},
+                mongodb::bson::doc! {"$set": {"active": false, "breaker": mongodb::bson::to_bson(breaker)?}},
+                None,
+            )
+            .await;
+    }
+
+    pub async fn resume_robot(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("robots")
+            .update_one(
+                mongodb::bson::doc! {"_id": robot_id.unique, "breaker": {"$ne": null}},
+                mongodb::bson::doc! {"$set": {"active": true, "breaker.acknowledged": mongodb::bson::to_bson(&now)?}},
+                None,
+            )
+            .await;
+    }
+
+    pub async fn set_robot_last_run(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+        last_run: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("robots")
+            .update_one(
+                mongodb::bson::doc! {"_id": robot_id.unique},
+                mongodb::bson::doc! {"$set": {"lastRun": mongodb::bson::to_bson(&last_run)?}},
+                None,
+            )
+            .await;
+    }
+
     pub async fn add_purge_log(
         &self,
         log: &models::purge::PurgeLog,
-    ) -> Result<mongodb::results::InsertOneResult, mongodb::error::Error> {
+    ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
+        // A removal is logged once, even when it's finished again after a crash
         return self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<mongodb::bson::Document>("purge_logs")
-            .insert_one(mongodb::bson::to_document(&log).unwrap(), None)
+            .update_one(
+                mongodb::bson::doc! {"purge": log.purge},
+                mongodb::bson::doc! {"$setOnInsert": mongodb::bson::to_document(&log).unwrap()},
+                mongodb::options::UpdateOptions::builder()
+                    .upsert(true)
+                    .build(),
+            )
             .await;
     }
 
@@ -118,7 +204,7 @@ impl Client {
     ) -> Result<Vec<models::purge::PurgeLog>, mongodb::error::Error> {
         return futures::TryStreamExt::try_collect(
             self.client
-                .database("robots")
+                .database(&self.database)
                 .collection::<models::purge::PurgeLog>("purge_logs")
                 .find(None, None)
                 .await?,
@@ -132,7 +218,7 @@ impl Client {
     ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
         return self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<mongodb::bson::Document>("purge_users")
             .update_one(
                 mongodb::bson::doc! {"user": mongodb::bson::to_document(&purge.user).unwrap()},
@@ -149,7 +235,7 @@ impl Client {
     ) -> Result<Vec<models::purge::PurgeData>, mongodb::error::Error> {
         return futures::TryStreamExt::try_collect(
             self.client
-                .database("robots")
+                .database(&self.database)
                 .collection::<models::purge::PurgeData>("purge_users")
                 .find(None, None)
                 .await?,
@@ -163,9 +249,9 @@ impl Client {
     ) -> Result<mongodb::results::DeleteResult, mongodb::error::Error> {
         return self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<mongodb::bson::Document>("purge_users")
-            .delete_one(mongodb::bson::to_document(&purge).unwrap(), None)
+            .delete_one(mongodb::bson::doc! {"_id": purge.id}, None)
             .await;
     }
 
@@ -175,7 +261,7 @@ impl Client {
     ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
         return self
             .client
-            .database("robots")
+            .database(&self.database)
             .collection::<mongodb::bson::Document>("purge_users")
             .update_one(
                 mongodb::bson::doc! {"_id":purge.id},
@@ -184,4 +270,389 @@ impl Client {
             )
             .await;
     }
+
+    pub async fn add_purge_invitation(
+        &self,
+        invitation: &models::purge::PurgeInvitation,
+    ) -> Result<mongodb::results::InsertOneResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("purge_invitations")
+            .insert_one(mongodb::bson::to_document(&invitation).unwrap(), None)
+            .await;
+    }
+
+    pub async fn get_purge_invitations(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+    ) -> Result<Vec<models::purge::PurgeInvitation>, mongodb::error::Error> {
+        return futures::TryStreamExt::try_collect(
+            self.client
+                .database(&self.database)
+                .collection::<models::purge::PurgeInvitation>("purge_invitations")
+                .find(mongodb::bson::doc! {"robot.id": robot_id.unique}, None)
+                .await?,
+        )
+        .await;
+    }
+
+    pub async fn approve_purge_user(
+        &self,
+        purge_id: &models::purge::PurgeIdentifier,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<models::purge::PurgeData>, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::purge::PurgeData>("purge_users")
+            .find_one_and_update(
+                mongodb::bson::doc! {"_id": purge_id.unique, "protected": true},
+                mongodb::bson::doc! {"$set": {"approved": mongodb::bson::to_bson(&now)?}},
+                mongodb::options::FindOneAndUpdateOptions::builder()
+                    .return_document(mongodb::options::ReturnDocument::After)
+                    .build(),
+            )
+            .await;
+    }
+
+    pub async fn retry_purge_user(
+        &self,
+        purge_id: &models::purge::PurgeIdentifier,
+    ) -> Result<Option<models::purge::PurgeData>, mongodb::error::Error> {
+        // The entry stays failed, so the next run retries the removal from the start
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::purge::PurgeData>("purge_users")
+            .find_one_and_update(
+                mongodb::bson::doc! {"_id": purge_id.unique, "state": mongodb::bson::to_bson(&models::purge::PurgeState::Failed)?},
+                mongodb::bson::doc! {"$set": {"attempts": 0}},
+                mongodb::options::FindOneAndUpdateOptions::builder()
+                    .return_document(mongodb::options::ReturnDocument::After)
+                    .build(),
+            )
+            .await;
+    }
+
+    pub async fn get_purge_user(
+        &self,
+        purge_id: &models::purge::PurgeIdentifier,
+    ) -> Result<Option<models::purge::PurgeData>, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::purge::PurgeData>("purge_users")
+            .find_one(mongodb::bson::doc! {"_id": purge_id.unique}, None)
+            .await;
+    }
+
+    pub async fn start_purge_user(
+        &self,
+        purge: &models::purge::PurgeData,
+        state: models::purge::PurgeState,
+    ) -> Result<Option<models::purge::PurgeData>, mongodb::error::Error> {
+        // Entries queued before they had a state count as queued
+        let mut states = vec![mongodb::bson::to_bson(&state)?];
+        if state == models::purge::PurgeState::Queued {
+            states.push(mongodb::bson::Bson::Null);
+        }
+
+        // Only matches while the entry is still in the state it was loaded in, so a kept account isn't removed
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::purge::PurgeData>("purge_users")
+            .find_one_and_update(
+                mongodb::bson::doc! {"_id": purge.id, "state": {"$in": states}},
+                mongodb::bson::doc! {"$set": mongodb::bson::to_document(&purge).unwrap()},
+                mongodb::options::FindOneAndUpdateOptions::builder()
+                    .return_document(mongodb::options::ReturnDocument::After)
+                    .build(),
+            )
+            .await;
+    }
+
+    pub async fn keep_purge_user(
+        &self,
+        purge_id: &models::purge::PurgeIdentifier,
+    ) -> Result<Option<models::purge::PurgeData>, mongodb::error::Error> {
+        // Only entries the scheduler hasn't started removing can be kept
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::purge::PurgeData>("purge_users")
+            .find_one_and_delete(
+                mongodb::bson::doc! {
+                    "_id": purge_id.unique,
+                    "state": {"$in": [
+                        mongodb::bson::to_bson(&models::purge::PurgeState::Queued)?,
+                        mongodb::bson::to_bson(&models::purge::PurgeState::Warned)?,
+                        null,
+                    ]},
+                    "attempts": {"$in": [0, null]},
+                },
+                None,
+            )
+            .await;
+    }
+
+    pub async fn add_purge_exemption(
+        &self,
+        exemption: &models::purge::PurgeExemption,
+    ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("purge_exemptions")
+            .update_one(
+                mongodb::bson::doc! {"purge": exemption.purge},
+                mongodb::bson::doc! {"$setOnInsert": mongodb::bson::to_document(&exemption).unwrap()},
+                mongodb::options::UpdateOptions::builder()
+                    .upsert(true)
+                    .build(),
+            )
+            .await;
+    }
+
+    pub async fn get_purge_exemption(
+        &self,
+        purge_id: &models::purge::PurgeIdentifier,
+    ) -> Result<Option<models::purge::PurgeExemption>, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::purge::PurgeExemption>("purge_exemptions")
+            .find_one(mongodb::bson::doc! {"purge": purge_id.unique}, None)
+            .await;
+    }
+
+    pub async fn delete_purge_exemption(
+        &self,
+        purge_id: &models::purge::PurgeIdentifier,
+    ) -> Result<mongodb::results::DeleteResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("purge_exemptions")
+            .delete_one(mongodb::bson::doc! {"purge": purge_id.unique}, None)
+            .await;
+    }
+
+    pub async fn get_purge_exemptions(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+    ) -> Result<Vec<models::purge::PurgeExemption>, mongodb::error::Error> {
+        return futures::TryStreamExt::try_collect(
+            self.client
+                .database(&self.database)
+                .collection::<models::purge::PurgeExemption>("purge_exemptions")
+                .find(mongodb::bson::doc! {"robot.id": robot_id.unique}, None)
+                .await?,
+        )
+        .await;
+    }
+
+    pub async fn add_purge_decision(
+        &self,
+        decision: &models::purge::PurgeDecision,
+    ) -> Result<mongodb::results::InsertOneResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("purge_decisions")
+            .insert_one(mongodb::bson::to_document(&decision).unwrap(), None)
+            .await;
+    }
+
+    pub async fn get_purge_decisions(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+    ) -> Result<Vec<models::purge::PurgeDecision>, mongodb::error::Error> {
+        return futures::TryStreamExt::try_collect(
+            self.client
+                .database(&self.database)
+                .collection::<models::purge::PurgeDecision>("purge_decisions")
+                .find(mongodb::bson::doc! {"robot.id": robot_id.unique}, None)
+                .await?,
+        )
+        .await;
+    }
+
+    pub async fn get_snapshot(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+    ) -> Result<Option<models::snapshot::Snapshot>, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::snapshot::Snapshot>("snapshots")
+            .find_one(mongodb::bson::doc! {"_id": robot_id.unique}, None)
+            .await;
+    }
+
+    pub async fn set_snapshot(
+        &self,
+        snapshot: &models::snapshot::Snapshot,
+    ) -> Result<mongodb::results::UpdateResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<models::snapshot::Snapshot>("snapshots")
+            .replace_one(
+                mongodb::bson::doc! {"_id": snapshot.robot},
+                snapshot,
+                mongodb::options::ReplaceOptions::builder()
+                    .upsert(true)
+                    .build(),
+            )
+            .await;
+    }
+
+    pub async fn get_snapshot_users(
+        &self,
+        snapshot: &models::snapshot::Snapshot,
+    ) -> Result<Vec<models::snapshot::SnapshotUser>, mongodb::error::Error> {
+        return futures::TryStreamExt::try_collect(
+            self.client
+                .database(&self.database)
+                .collection::<models::snapshot::SnapshotUser>("snapshot_users")
+                .find(
+                    mongodb::bson::doc! {"robot": snapshot.robot, "version": snapshot.version},
+                    None,
+                )
+                .await?,
+        )
+        .await;
+    }
+
+    pub async fn set_snapshot_users(
+        &self,
+        users: &[models::snapshot::SnapshotUser],
+    ) -> Result<(), mongodb::error::Error> {
+        if users.is_empty() {
+            return Ok(());
+        }
+
+        self.client
+            .database(&self.database)
+            .collection::<models::snapshot::SnapshotUser>("snapshot_users")
+            .insert_many(users, None)
+            .await?;
+        return Ok(());
+    }
+
+    pub async fn delete_snapshot_users(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+        versions: &[Option<mongodb::bson::oid::ObjectId>],
+    ) -> Result<mongodb::results::DeleteResult, mongodb::error::Error> {
+        // Every version of the robot's users but the given ones
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("snapshot_users")
+            .delete_many(
+                mongodb::bson::doc! {"robot": robot_id.unique, "version": {"$nin": versions}},
+                None,
+            )
+            .await;
+    }
+
+    pub async fn delete_snapshot(
+        &self,
+        robot_id: &models::robot::RobotIdentifier,
+    ) -> Result<mongodb::results::DeleteResult, mongodb::error::Error> {
+        self.client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("snapshot_users")
+            .delete_many(mongodb::bson::doc! {"robot": robot_id.unique}, None)
+            .await?;
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("snapshots")
+            .delete_one(mongodb::bson::doc! {"_id": robot_id.unique}, None)
+            .await;
+    }
+
+    pub async fn acquire_lock(
+        &self,
+        name: &String,
+        owner: &String,
+        now: chrono::DateTime<chrono::Utc>,
+        lease: chrono::Duration,
+    ) -> Result<bool, mongodb::error::Error> {
+        // A lock held by another owner doesn't match, so the upsert collides with its id
+        let result = self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("locks")
+            .update_one(
+                mongodb::bson::doc! {
+                    "_id": name,
+                    "$or": [
+                        {"owner": owner},
+                        {"expires": {"$lte": mongodb::bson::DateTime::from_millis(now.timestamp_millis())}},
+                    ],
+                },
+                mongodb::bson::doc! {"$set": {
+                    "owner": owner,
+                    "expires": mongodb::bson::DateTime::from_millis((now + lease).timestamp_millis()),
+                    "heartbeat": mongodb::bson::DateTime::from_millis(now.timestamp_millis()),
+                }},
+                mongodb::options::UpdateOptions::builder().upsert(true).build(),
+            )
+            .await;
+        return match result {
+            Ok(_result) => Ok(true),
+            Err(error) => match error.kind.as_ref() {
+                mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(
+                    write_error,
+                )) if write_error.code == 11000 => Ok(false),
+                _ => Err(error),
+            },
+        };
+    }
+
+    pub async fn renew_lock(
+        &self,
+        name: &String,
+        owner: &String,
+        now: chrono::DateTime<chrono::Utc>,
+        lease: chrono::Duration,
+    ) -> Result<bool, mongodb::error::Error> {
+        let result = self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("locks")
+            .update_one(
+                mongodb::bson::doc! {"_id": name, "owner": owner},
+                mongodb::bson::doc! {"$set": {
+                    "expires": mongodb::bson::DateTime::from_millis((now + lease).timestamp_millis()),
+                    "heartbeat": mongodb::bson::DateTime::from_millis(now.timestamp_millis()),
+                }},
+                None,
+            )
+            .await?;
+        return Ok(result.matched_count > 0);
+    }
+
+    pub async fn release_lock(
+        &self,
+        name: &String,
+        owner: &String,
+    ) -> Result<mongodb::results::DeleteResult, mongodb::error::Error> {
+        return self
+            .client
+            .database(&self.database)
+            .collection::<mongodb::bson::Document>("locks")
+            .delete_one(mongodb::bson::doc! {"_id": name, "owner": owner}, None)
+            .await;
+    }
+
+    #[cfg(test)]
+    pub async fn drop_database(&self) -> Result<(), mongodb::error::Error> {
+        return self.client.database(&self.database).drop(None).await;
+    }
 }
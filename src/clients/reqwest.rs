@@ -4,96 +4,1211 @@ use crate::*;
 pub struct Client {
     client: reqwest::Client,
     config: configs::reqwest::Config,
+    limiter: std::sync::Arc<utils::limiter::Limiter>,
 }
 
 impl Client {
     pub fn new(config: configs::reqwest::Config) -> Self {
         return Self {
             client: reqwest::Client::new(),
+            limiter: std::sync::Arc::new(utils::limiter::Limiter::new(config.rate, config.burst)),
             config,
         };
     }
 
-    pub async fn check_jira_credentials(
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let request = request
+            .timeout(std::time::Duration::from_secs(self.config.timeout))
+            .build()?;
+        let host = request.url().host_str().unwrap_or_default().to_string();
+
+        // Only requests that are safe to repeat are retried, a retried POST could purge a user twice
+        let idempotent = matches!(
+            *request.method(),
+            reqwest::Method::GET | reqwest::Method::HEAD
+        );
+
+        let mut attempt = 0;
+        loop {
+            self.limiter.acquire(&host).await;
+
+            // Requests with a streamed body can't be replayed, so they only get one attempt
+            let result = match request.try_clone() {
+                Some(request) => self.client.execute(request).await,
+                None => return self.client.execute(request).await,
+            };
+
+            let retry = match &result {
+                Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| {
+                            utils::limiter::parse_retry_after(value, chrono::Utc::now())
+                        });
+                    if let Some(retry_after) = retry_after {
+                        self.limiter.pause(&host, retry_after);
+                    }
+                    Some(retry_after)
+                }
+                Ok(response) if response.status().is_server_error() => Some(None),
+                Err(error) if error.is_timeout() || error.is_connect() => Some(None),
+                _ => None,
+            };
+
+            match retry {
+                Some(retry_after) if idempotent && attempt < self.config.retries => {
+                    let delay = retry_after.unwrap_or_else(|| {
+                        utils::limiter::backoff(
+                            std::time::Duration::from_millis(self.config.backoff),
+                            attempt,
+                        )
+                    });
+                    log::warn!(
+                        "Retrying request to {} in {:?} (attempt {} of {})",
+                        request.url().path(),
+                        delay,
+                        attempt + 1,
+                        self.config.retries
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    fn authorization(&self, robot: &models::robot::Robot) -> String {
+        if robot.config.credential.platform_type == models::robot::RobotPlatform::JiraDataCenter {
+            return format!("Bearer {}", robot.config.credential.personal_access_token);
+        }
+        return format!(
+            "Basic {}",
+            base64::encode(format!(
+                "{}:{}",
+                robot.config.credential.platform_email, robot.config.credential.platform_api_key
+            ))
+        );
+    }
+
+    fn get_jira_url(&self, robot: &models::robot::Robot, path: &str) -> String {
+        return match robot.config.credential.platform_type {
+            models::robot::RobotPlatform::JiraDataCenter => format!(
+                "{}/rest/api/2{}",
+                robot.config.credential.platform_url.trim_end_matches('/'),
+                path
+            ),
+            _ => format!("{}/rest/api/3{}", self.config.site_url, path),
+        };
+    }
+
+    fn get_confluence_url(&self, path: &str) -> String {
+        return format!("{}/wiki/rest/api{}", self.config.site_url, path);
+    }
+
+    fn get_bitbucket_url(&self, path: &str) -> String {
+        return format!("{}{}", self.config.bitbucket_url, path);
+    }
+
+    fn get_user_parameter(&self, robot: &models::robot::Robot) -> &'static str {
+        return match robot.config.credential.platform_type {
+            models::robot::RobotPlatform::JiraDataCenter => "username",
+            _ => "accountId",
+        };
+    }
+
+    async fn read<T: serde::de::DeserializeOwned>(
+        &self,
+        response: Result<reqwest::Response, reqwest::Error>,
+    ) -> Result<T, errors::atlassian::Error> {
+        let response = response?;
+        match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                return Err(errors::atlassian::Error::Unauthorized);
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                return Err(errors::atlassian::Error::RateLimited {
+                    retry_after: response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| {
+                            utils::limiter::parse_retry_after(value, chrono::Utc::now())
+                        })
+                        .map(|retry_after| retry_after.as_secs()),
+                });
+            }
+            status if !status.is_success() => {
+                return Err(errors::atlassian::Error::Status(status));
+            }
+            _ => {}
+        }
+
+        let text = response.text().await?;
+        return serde_json::from_str(text.as_str()).map_err(errors::atlassian::Error::Schema);
+    }
+
+    pub async fn check_credentials(
         &self,
         robot: &models::robot::Robot,
     ) -> Result<bool, reqwest::Error> {
+        let url = match robot.config.credential.platform_type {
+            models::robot::RobotPlatform::ConfluenceCloud => {
+                self.get_confluence_url("/user/current")
+            }
+            models::robot::RobotPlatform::BitbucketCloud => self.get_bitbucket_url(&format!(
+                "/2.0/workspaces/{}",
+                robot.config.credential.workspace
+            )),
+            _ => self.get_jira_url(robot, "/myself"),
+        };
         let response = self
-            .client
-            .get("https://telkomdevelopernetwork.atlassian.net/rest/api/3/myself")
-            .header(
-                reqwest::header::AUTHORIZATION,
-                format!(
-                    "Basic {}",
-                    base64::encode(&format!(
-                        "{}:{}",
-                        robot.config.credential.platform_email,
-                        robot.config.credential.platform_api_key
-                    ))
-                ),
+            .send(
+                self.client
+                    .get(url)
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
             )
-            .send()
             .await?;
         return Ok(response.status() == reqwest::StatusCode::OK);
     }
 
-    pub async fn get_jira_users(&self, cloud_session_token: &String) -> Vec<models::jira::User> {
+    async fn get_jira_users_page(
+        &self,
+        cloud_session_token: &String,
+        start_index: i32,
+    ) -> Result<models::jira::Users, errors::atlassian::Error> {
+        let response = self
+            .send(
+                self.client
+                    .get(format!(
+                        "{}/gateway/api/adminhub/um/org/{}/users?count=100&start-index={}",
+                        self.config.admin_url, self.config.organization_id, start_index
+                    ))
+                    .header(
+                        reqwest::header::COOKIE,
+                        format!("cloud.session.token={}", cloud_session_token),
+                    ),
+            )
+            .await?;
+
+        // An expired session is redirected to the login page instead of being rejected
+        if response.url().host_str() == Some("id.atlassian.com") {
+            return Err(errors::atlassian::Error::Unauthorized);
+        }
+        return self.read(Ok(response)).await;
+    }
+
+    pub async fn get_jira_users(
+        &self,
+        cloud_session_token: &String,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
         let mut users: Vec<models::jira::User> = vec![];
+        let mut total = 0;
         let mut start_index = 1;
         loop {
-            let response = self
-            .client
-            .get(format!("https://admin.atlassian.com/gateway/api/adminhub/um/org/{}/users?count=100&start-index={}", self.config.organization_id, start_index))
-            .header(reqwest::header::COOKIE, format!("cloud.session.token={}", cloud_session_token))
-            .send()
-            .await;
-            if response.is_err() {
+            let data = match self
+                .get_jira_users_page(cloud_session_token, start_index)
+                .await
+            {
+                Ok(data) => data,
+                Err(error) => {
+                    // Nothing was read, so this isn't a partial result
+                    if users.is_empty() {
+                        return Err(error);
+                    }
+                    return Err(errors::atlassian::Error::Partial {
+                        fetched: users.len(),
+                        total: Some(total),
+                        cause: Some(Box::new(error)),
+                    });
+                }
+            };
+
+            total = data.total;
+            let count = data.users.len();
+            users.extend(data.users);
+            if count == 0 || total <= start_index + 99 {
                 break;
             }
 
-            let text = response.unwrap().text().await;
-            if text.is_err() {
+            start_index += 100;
+        }
+
+        // The organization can change between pages, so make sure we have everyone
+        if (users.len() as i32) < total {
+            return Err(errors::atlassian::Error::Partial {
+                fetched: users.len(),
+                total: Some(total),
+                cause: None,
+            });
+        }
+        return Ok(users);
+    }
+
+    pub async fn get_organization_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        let mut users: Vec<models::jira::User> = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(format!(
+                    "{}/admin/v1/orgs/{}/users",
+                    self.config.api_url, self.config.organization_id
+                ))
+                .bearer_auth(&robot.config.credential.admin_api_key);
+            if let Some(cursor) = &cursor {
+                request = request.query(&[("cursor", cursor)]);
+            }
+
+            let response = self.send(request).await;
+            let data = match self.read::<models::jira::OrganizationUsers>(response).await {
+                Ok(data) => data,
+                Err(error) if users.is_empty() => return Err(error),
+                Err(error) => {
+                    return Err(errors::atlassian::Error::Partial {
+                        fetched: users.len(),
+                        total: None,
+                        cause: Some(Box::new(error)),
+                    })
+                }
+            };
+
+            users.extend(data.data.iter().map(|user| user.to_user()));
+            cursor = data.links.next;
+            if cursor.is_none() || data.data.is_empty() {
                 break;
             }
+        }
+        return Ok(users);
+    }
+
+    async fn get_data_center_permission_holders(
+        &self,
+        robot: &models::robot::Robot,
+        permission: &str,
+    ) -> Result<std::collections::BTreeSet<String>, errors::atlassian::Error> {
+        let mut usernames = std::collections::BTreeSet::new();
+        let mut start_at = 0;
+        loop {
+            // "." matches every username, only active users are returned
+            let data = self
+                .read::<Vec<models::jira::DataCenterUser>>(
+                    self.send(
+                        self.client
+                            .get(self.get_jira_url(robot, "/user/permission/search"))
+                            .query(&[
+                                ("username", "."),
+                                ("permissions", permission),
+                                ("startAt", start_at.to_string().as_str()),
+                                ("maxResults", "1000"),
+                            ])
+                            .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                    )
+                    .await,
+                )
+                .await?;
 
-            let data: Result<models::jira::Users, _> = serde_json::from_str(text.unwrap().as_str());
-            if data.is_err() {
+            let count = data.len();
+            usernames.extend(data.into_iter().map(|user| user.name));
+            if count < 1000 {
                 break;
             }
 
-            users.extend(data.as_ref().unwrap().users.clone());
-            if data.unwrap().total <= start_index + 99 {
+            start_at += 1000;
+        }
+        return Ok(usernames);
+    }
+
+    pub async fn get_data_center_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        let mut users: Vec<models::jira::User> = vec![];
+        let mut start_at = 0;
+        loop {
+            // "." matches every username
+            let response = self
+                .send(
+                    self.client
+                        .get(self.get_jira_url(robot, "/user/search"))
+                        .query(&[
+                            ("username", "."),
+                            ("includeActive", "true"),
+                            ("includeInactive", "true"),
+                            ("startAt", start_at.to_string().as_str()),
+                            ("maxResults", "1000"),
+                        ])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await;
+
+            let data = match self
+                .read::<Vec<models::jira::DataCenterUser>>(response)
+                .await
+            {
+                Ok(data) => data,
+                Err(error) if users.is_empty() => return Err(error),
+                Err(error) => {
+                    return Err(errors::atlassian::Error::Partial {
+                        fetched: users.len(),
+                        total: None,
+                        cause: Some(Box::new(error)),
+                    })
+                }
+            };
+
+            let count = data.len();
+            for user in data {
+                // Without a last login every user would look inactive, so the run fails instead
+                let last_login = user.get_last_login().map_err(|error| {
+                    errors::atlassian::Error::Schema(serde::de::Error::custom(error))
+                })?;
+                users.push(user.to_user(last_login));
+            }
+            if count < 1000 {
                 break;
             }
 
-            start_index += 100;
+            start_at += 1000;
+        }
+
+        // Jira administrators are protected like site admins in the cloud
+        let administrators = self
+            .get_data_center_permission_holders(robot, "ADMINISTER")
+            .await?;
+        let system_administrators = self
+            .get_data_center_permission_holders(robot, "SYSTEM_ADMIN")
+            .await?;
+        for user in users.iter_mut() {
+            user.site_admin = administrators.contains(&user.id);
+            user.sys_admin = system_administrators.contains(&user.id);
+        }
+        return Ok(users);
+    }
+
+    pub async fn get_jira_group_members(
+        &self,
+        robot: &models::robot::Robot,
+        group: &str,
+    ) -> Result<std::collections::HashSet<String>, reqwest::Error> {
+        let mut members = std::collections::HashSet::new();
+        let mut start_at = 0;
+        loop {
+            let data = self
+                .send(
+                    self.client
+                        .get(self.get_jira_url(robot, "/group/member"))
+                        .query(&[
+                            ("groupname", group),
+                            ("includeInactiveUsers", "true"),
+                            ("startAt", start_at.to_string().as_str()),
+                            ("maxResults", "50"),
+                        ])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?
+                .error_for_status()?
+                .json::<models::jira::GroupMembers>()
+                .await?;
+
+            let is_last = data.is_last || data.values.is_empty();
+            members.extend(data.values.into_iter().map(|member| member.account_id));
+            if is_last {
+                break;
+            }
+
+            start_at += 50;
+        }
+        return Ok(members);
+    }
+
+    pub async fn get_jira_group_memberships(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<models::jira::GroupMemberships, reqwest::Error> {
+        let mut memberships = models::jira::GroupMemberships::new();
+        for group in robot.get_groups() {
+            let members = self.get_jira_group_members(robot, group).await?;
+            memberships.insert(group.clone(), members);
+        }
+        return Ok(memberships);
+    }
+
+    pub async fn get_jira_projects(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::ProjectDetails>, reqwest::Error> {
+        return self
+            .send(
+                self.client
+                    .get(self.get_jira_url(robot, "/project"))
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?
+            .error_for_status()?
+            .json::<Vec<models::jira::ProjectDetails>>()
+            .await;
+    }
+
+    pub async fn get_jira_project_roles(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::ProjectRole>, reqwest::Error> {
+        return self
+            .send(
+                self.client
+                    .get(self.get_jira_url(robot, "/role"))
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?
+            .error_for_status()?
+            .json::<Vec<models::jira::ProjectRole>>()
+            .await;
+    }
+
+    pub async fn get_jira_project_role_actors(
+        &self,
+        robot: &models::robot::Robot,
+        project_id: &String,
+        role_id: i64,
+    ) -> Result<Vec<models::jira::RoleActor>, reqwest::Error> {
+        let role = self
+            .send(
+                self.client
+                    .get(
+                        self.get_jira_url(
+                            robot,
+                            &format!("/project/{}/role/{}", project_id, role_id),
+                        ),
+                    )
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?
+            .error_for_status()?
+            .json::<models::jira::ProjectRole>()
+            .await?;
+        return Ok(role.actors.unwrap_or_default());
+    }
+
+    pub async fn get_jira_project_role_holders(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<std::collections::HashSet<String>, reqwest::Error> {
+        let projects = self.get_jira_projects(robot).await?;
+        let roles = self.get_jira_project_roles(robot).await?;
+
+        let mut holders = std::collections::HashSet::new();
+        let mut groups = std::collections::HashSet::new();
+        for project in &projects {
+            for role in &roles {
+                for actor in self
+                    .get_jira_project_role_actors(robot, &project.id, role.id)
+                    .await?
+                {
+                    if let Some(actor_user) = actor.actor_user {
+                        holders.insert(actor_user.account_id);
+                    }
+                    if let Some(actor_group) = actor.actor_group {
+                        groups.insert(actor_group.name);
+                    }
+
+                    // Data Center only names the actor and tells its type
+                    match (actor.actor_type.as_deref(), actor.name) {
+                        (Some("atlassian-user-role-actor"), Some(name)) => {
+                            holders.insert(name);
+                        }
+                        (Some("atlassian-group-role-actor"), Some(name)) => {
+                            groups.insert(name);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // Roles granted through a group count for every member of that group
+        for group in &groups {
+            holders.extend(self.get_jira_group_members(robot, group).await?);
+        }
+        return Ok(holders);
+    }
+
+    pub async fn get_jira_issue_activity(
+        &self,
+        robot: &models::robot::Robot,
+        account_id: &String,
+        days: i64,
+    ) -> Result<i64, reqwest::Error> {
+        // `updatedBy` matches issues the account created, edited, transitioned or commented on
+        let jql = format!(
+            "issue in updatedBy(\"{0}\", \"-{1}d\") OR (creator = \"{0}\" AND created >= -{1}d) OR (assignee = \"{0}\" AND updated >= -{1}d)",
+            account_id, days
+        );
+        let search = self
+            .send(
+                self.client
+                    .get(self.get_jira_url(robot, "/search"))
+                    .query(&[("jql", jql.as_str()), ("maxResults", "0"), ("fields", "id")])
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?
+            .error_for_status()?
+            .json::<models::jira::Search>()
+            .await?;
+        return Ok(search.total);
+    }
+
+    pub async fn resend_jira_invitation(
+        &self,
+        robot: &models::robot::Robot,
+        user: &models::jira::User,
+    ) -> Result<bool, reqwest::Error> {
+        // Data Center has no invitations to re-send
+        if robot.config.credential.platform_type == models::robot::RobotPlatform::JiraDataCenter {
+            return Ok(false);
+        }
+
+        let response = self
+            .send(
+                self.client
+                    .post(format!(
+                        "{}/gateway/api/adminhub/um/org/{}/users/{}/resend-invite",
+                        self.config.admin_url, self.config.organization_id, user.id
+                    ))
+                    .header(
+                        reqwest::header::COOKIE,
+                        format!(
+                            "cloud.session.token={}",
+                            robot.config.credential.cloud_session_token
+                        ),
+                    ),
+            )
+            .await?;
+        return Ok(response.status().is_success());
+    }
+
+    pub async fn get_jira_application_groups(
+        &self,
+        robot: &models::robot::Robot,
+        application_key: &String,
+    ) -> Result<Vec<String>, reqwest::Error> {
+        let application_role = self
+            .send(
+                self.client
+                    .get(self.get_jira_url(robot, &format!("/applicationrole/{}", application_key)))
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?
+            .error_for_status()?
+            .json::<models::jira::ApplicationRole>()
+            .await?;
+        return Ok(application_role.groups);
+    }
+
+    pub async fn get_jira_user_groups(
+        &self,
+        robot: &models::robot::Robot,
+        user_id: &str,
+    ) -> Result<Vec<String>, reqwest::Error> {
+        let groups = match robot.config.credential.platform_type {
+            models::robot::RobotPlatform::JiraDataCenter => {
+                self.send(
+                    self.client
+                        .get(self.get_jira_url(robot, "/user"))
+                        .query(&[("username", user_id), ("expand", "groups")])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?
+                .error_for_status()?
+                .json::<models::jira::UserGroups>()
+                .await?
+                .groups
+                .items
+            }
+            _ => {
+                self.send(
+                    self.client
+                        .get(self.get_jira_url(robot, "/user/groups"))
+                        .query(&[("accountId", user_id)])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?
+                .error_for_status()?
+                .json::<Vec<models::jira::GroupName>>()
+                .await?
+            }
+        };
+        return Ok(groups.into_iter().map(|group| group.name).collect());
+    }
+
+    pub async fn remove_user_from_jira_groups(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        groups: &[String],
+    ) -> Result<bool, reqwest::Error> {
+        // Jira refuses to remove a user from a group they aren't in, so only their own groups are removed
+        let user_groups = self.get_jira_user_groups(robot, &purge.user.id).await?;
+        let mut removed = true;
+        for group in groups.iter().filter(|group| user_groups.contains(group)) {
+            let response = self
+                .send(
+                    self.client
+                        .delete(self.get_jira_url(robot, "/group/user"))
+                        .query(&[
+                            ("groupname", group.as_str()),
+                            (self.get_user_parameter(robot), purge.user.id.as_str()),
+                        ])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?;
+            if !response.status().is_success() {
+                log::warn!(
+                    "Failed to remove user {:?} from group {:?} ({})",
+                    purge.user.display_name,
+                    group,
+                    response.status()
+                );
+                removed = false;
+            }
         }
-        return users;
+        return Ok(removed);
+    }
+
+    pub async fn deactivate_user_in_organization(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+    ) -> Result<bool, reqwest::Error> {
+        // Robots reading the Organizations API have no admin hub session, so they suspend access with the API key
+        if robot.config.credential.user_source == models::robot::RobotSource::Organization {
+            let response = self
+                .send(
+                    self.client
+                        .post(format!(
+                            "{}/admin/v1/orgs/{}/directory/users/{}/suspend-access",
+                            self.config.api_url, self.config.organization_id, purge.user.id
+                        ))
+                        .bearer_auth(&robot.config.credential.admin_api_key),
+                )
+                .await?;
+            return Ok(response.status().is_success());
+        }
+
+        let response = self
+            .send(
+                self.client
+                    .post(format!(
+                        "{}/gateway/api/adminhub/um/org/{}/users/{}/deactivate",
+                        self.config.admin_url, self.config.organization_id, purge.user.id
+                    ))
+                    .header(
+                        reqwest::header::COOKIE,
+                        format!(
+                            "cloud.session.token={}",
+                            robot.config.credential.cloud_session_token
+                        ),
+                    ),
+            )
+            .await?;
+        return Ok(response.status().is_success());
+    }
+
+    pub async fn deactivate_user_in_data_center(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+    ) -> Result<bool, reqwest::Error> {
+        let response = self
+            .send(
+                self.client
+                    .put(self.get_jira_url(robot, "/user"))
+                    .query(&[("username", &purge.user.id)])
+                    .json(&serde_json::json!({ "active": false }))
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?;
+        return Ok(response.status().is_success());
     }
 
     pub async fn remove_user_from_jira(
         &self,
         robot: &models::robot::Robot,
         purge: &models::purge::PurgeData,
-    ) -> Result<reqwest::Response, reqwest::Error> {
+    ) -> Result<bool, reqwest::Error> {
+        let response = self
+            .send(
+                self.client
+                    .delete(self.get_jira_url(robot, "/user"))
+                    .query(&[(self.get_user_parameter(robot), purge.user.id.as_str())])
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?;
+        return Ok(response.status() == reqwest::StatusCode::NO_CONTENT);
+    }
+
+    pub async fn purge_user(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        action: &models::purge::PurgeAction,
+    ) -> Result<bool, reqwest::Error> {
+        return match action {
+            models::purge::PurgeAction::RevokeProduct { product } if product == "confluence" => {
+                self.revoke_confluence_access(robot, purge).await
+            }
+            models::purge::PurgeAction::RevokeProduct { product } => {
+                let groups = self.get_jira_application_groups(robot, product).await?;
+                self.remove_user_from_jira_groups(robot, purge, &groups)
+                    .await
+            }
+            models::purge::PurgeAction::RemoveFromGroups { groups } => {
+                self.remove_user_from_jira_groups(robot, purge, groups)
+                    .await
+            }
+            models::purge::PurgeAction::Deactivate => match robot.config.credential.platform_type {
+                models::robot::RobotPlatform::JiraDataCenter => {
+                    self.deactivate_user_in_data_center(robot, purge).await
+                }
+                _ => self.deactivate_user_in_organization(robot, purge).await,
+            },
+            models::purge::PurgeAction::Delete => self.remove_user_from_jira(robot, purge).await,
+        };
+    }
+
+    pub async fn get_confluence_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        let mut users: Vec<models::jira::User> = vec![];
+        let mut start = 0;
+        loop {
+            let response = self
+                .send(
+                    self.client
+                        .get(self.get_confluence_url("/search/user"))
+                        .query(&[
+                            ("cql", "type = user"),
+                            ("start", start.to_string().as_str()),
+                            ("limit", "100"),
+                        ])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await;
+
+            let data = match self.read::<models::confluence::Search>(response).await {
+                Ok(data) => data,
+                Err(error) if users.is_empty() => return Err(error),
+                Err(error) => {
+                    return Err(errors::atlassian::Error::Partial {
+                        fetched: users.len(),
+                        total: None,
+                        cause: Some(Box::new(error)),
+                    })
+                }
+            };
+
+            let count = data.results.len() as i64;
+            users.extend(
+                data.results
+                    .iter()
+                    .filter_map(|result| result.user.as_ref())
+                    .map(|user| user.to_user()),
+            );
+            if count == 0 || start + count >= data.total_size {
+                break;
+            }
+
+            start += count;
+        }
+        return Ok(users);
+    }
+
+    pub async fn get_confluence_activity(
+        &self,
+        robot: &models::robot::Robot,
+        account_id: &String,
+        days: i64,
+    ) -> Result<i64, reqwest::Error> {
+        // `contributor` matches content the account created, edited or commented on
+        let cql = format!(
+            "contributor = \"{}\" AND lastmodified >= now(\"-{}d\")",
+            account_id, days
+        );
+        let search = self
+            .send(
+                self.client
+                    .get(self.get_confluence_url("/search"))
+                    .query(&[("cql", cql.as_str()), ("limit", "1")])
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?
+            .error_for_status()?
+            .json::<models::confluence::Search>()
+            .await?;
+        return Ok(search.total_size);
+    }
+
+    pub async fn get_confluence_group_members(
+        &self,
+        robot: &models::robot::Robot,
+        group: &str,
+    ) -> Result<std::collections::HashSet<String>, reqwest::Error> {
+        let mut members = std::collections::HashSet::new();
+        let mut start = 0;
+        loop {
+            let data = self
+                .send(
+                    self.client
+                        .get(self.get_confluence_url("/group/member"))
+                        .query(&[
+                            ("name", group),
+                            ("start", start.to_string().as_str()),
+                            ("limit", "200"),
+                        ])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?
+                .error_for_status()?
+                .json::<models::confluence::Members>()
+                .await?;
+
+            let count = data.results.len();
+            members.extend(data.results.into_iter().map(|member| member.account_id));
+            if count < 200 {
+                break;
+            }
+
+            start += 200;
+        }
+        return Ok(members);
+    }
+
+    pub async fn get_confluence_group_memberships(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<models::jira::GroupMemberships, reqwest::Error> {
+        let mut memberships = models::jira::GroupMemberships::new();
+        for group in robot.get_groups() {
+            let members = self.get_confluence_group_members(robot, group).await?;
+            memberships.insert(group.clone(), members);
+        }
+        return Ok(memberships);
+    }
+
+    pub async fn get_confluence_user_groups(
+        &self,
+        robot: &models::robot::Robot,
+        account_id: &str,
+    ) -> Result<Vec<String>, reqwest::Error> {
+        let mut groups = vec![];
+        let mut start = 0;
+        loop {
+            let data = self
+                .send(
+                    self.client
+                        .get(self.get_confluence_url("/user/memberof"))
+                        .query(&[
+                            ("accountId", account_id),
+                            ("start", start.to_string().as_str()),
+                            ("limit", "200"),
+                        ])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?
+                .error_for_status()?
+                .json::<models::confluence::Groups>()
+                .await?;
+
+            let count = data.results.len();
+            groups.extend(data.results.into_iter().map(|group| group.name));
+            if count < 200 {
+                break;
+            }
+
+            start += 200;
+        }
+        return Ok(groups);
+    }
+
+    pub async fn remove_user_from_confluence_groups(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        groups: &Vec<String>,
+    ) -> Result<bool, reqwest::Error> {
+        let mut removed = true;
+        for group in groups {
+            let response = self
+                .send(
+                    self.client
+                        .delete(self.get_confluence_url("/group/user"))
+                        .query(&[("name", group), ("accountId", &purge.user.id)])
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?;
+            if !response.status().is_success() {
+                log::warn!(
+                    "Failed to remove user {:?} from Confluence group {:?} ({})",
+                    purge.user.display_name,
+                    group,
+                    response.status()
+                );
+                removed = false;
+            }
+        }
+        return Ok(removed);
+    }
+
+    pub async fn revoke_confluence_access(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+    ) -> Result<bool, reqwest::Error> {
+        // Confluence doesn't tell which groups grant it, the site's default access groups are named after it
+        let groups = self
+            .get_confluence_user_groups(robot, &purge.user.id)
+            .await?
+            .into_iter()
+            .filter(|group| group == "confluence-users" || group.starts_with("confluence-users-"))
+            .collect::<Vec<_>>();
         return self
-            .client
-            .delete(format!(
-                "https://telkomdevelopernetwork.atlassian.net/rest/api/latest/user?accountId={}",
-                purge.user.id
-            ))
-            .header(
-                reqwest::header::AUTHORIZATION,
-                format!(
-                    "Basic {}",
-                    base64::encode(&format!(
-                        "{}:{}",
-                        robot.config.credential.platform_email,
-                        robot.config.credential.platform_api_key
-                    ))
-                ),
+            .remove_user_from_confluence_groups(robot, purge, &groups)
+            .await;
+    }
+
+    pub async fn purge_confluence_user(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        action: &models::purge::PurgeAction,
+    ) -> Result<bool, reqwest::Error> {
+        return match action {
+            // Other products of the site are revoked through Jira, like for Jira robots
+            models::purge::PurgeAction::RevokeProduct { product } if product != "confluence" => {
+                self.purge_user(robot, purge, action).await
+            }
+            models::purge::PurgeAction::RevokeProduct { .. } => {
+                self.revoke_confluence_access(robot, purge).await
+            }
+            // Confluence can't delete users, so they lose every group
+            models::purge::PurgeAction::Delete => {
+                let groups = self
+                    .get_confluence_user_groups(robot, &purge.user.id)
+                    .await?;
+                self.remove_user_from_confluence_groups(robot, purge, &groups)
+                    .await
+            }
+            models::purge::PurgeAction::RemoveFromGroups { groups } => {
+                let groups = self
+                    .get_confluence_user_groups(robot, &purge.user.id)
+                    .await?
+                    .into_iter()
+                    .filter(|group| groups.contains(group))
+                    .collect::<Vec<_>>();
+                self.remove_user_from_confluence_groups(robot, purge, &groups)
+                    .await
+            }
+            models::purge::PurgeAction::Deactivate => {
+                self.deactivate_user_in_organization(robot, purge).await
+            }
+        };
+    }
+
+    pub async fn get_bitbucket_members(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        let mut users: Vec<models::jira::User> = vec![];
+        let mut next = Some(self.get_bitbucket_url(&format!(
+            "/2.0/workspaces/{}/members?pagelen=100",
+            robot.config.credential.workspace
+        )));
+        while let Some(url) = next {
+            let response = self
+                .send(
+                    self.client
+                        .get(url)
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await;
+
+            let data = match self.read::<models::bitbucket::Memberships>(response).await {
+                Ok(data) => data,
+                Err(error) if users.is_empty() => return Err(error),
+                Err(error) => {
+                    return Err(errors::atlassian::Error::Partial {
+                        fetched: users.len(),
+                        total: None,
+                        cause: Some(Box::new(error)),
+                    })
+                }
+            };
+
+            users.extend(
+                data.values
+                    .iter()
+                    .map(|membership| membership.user.to_user()),
+            );
+            next = data.next;
+        }
+        return Ok(users);
+    }
+
+    pub async fn get_bitbucket_activity(
+        &self,
+        robot: &models::robot::Robot,
+        account_id: &String,
+        days: i64,
+    ) -> Result<i64, reqwest::Error> {
+        // Pull requests the account authored or updated in the workspace
+        let query = format!(
+            "updated_on >= {}",
+            (chrono::Utc::now() - chrono::Duration::days(days))
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        );
+        let pull_requests = self
+            .send(
+                self.client
+                    .get(self.get_bitbucket_url(&format!(
+                        "/2.0/workspaces/{}/pullrequests/{}",
+                        robot.config.credential.workspace, account_id
+                    )))
+                    .query(&[
+                        ("q", query.as_str()),
+                        ("state", "OPEN"),
+                        ("state", "MERGED"),
+                        ("state", "DECLINED"),
+                        ("pagelen", "1"),
+                    ])
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
             )
-            .send()
+            .await?
+            .error_for_status()?
+            .json::<models::bitbucket::PullRequests>()
+            .await?;
+        return Ok(pull_requests
+            .size
+            .unwrap_or(pull_requests.values.len() as i64));
+    }
+
+    pub async fn get_bitbucket_groups(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::bitbucket::Group>, reqwest::Error> {
+        // Workspace groups are only available in the 1.0 API
+        return self
+            .send(
+                self.client
+                    .get(self.get_bitbucket_url(&format!(
+                        "/1.0/groups/{}",
+                        robot.config.credential.workspace
+                    )))
+                    .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+            )
+            .await?
+            .error_for_status()?
+            .json::<Vec<models::bitbucket::Group>>()
             .await;
     }
+
+    pub async fn get_bitbucket_group_memberships(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<models::jira::GroupMemberships, reqwest::Error> {
+        let groups = self.get_bitbucket_groups(robot).await?;
+        let mut memberships = models::jira::GroupMemberships::new();
+        for name in robot.get_groups() {
+            let members = groups
+                .iter()
+                .filter(|group| group.name == *name || group.slug == *name)
+                .flat_map(|group| group.members.iter().map(|member| member.get_id()))
+                .collect();
+            memberships.insert(name.clone(), members);
+        }
+        return Ok(memberships);
+    }
+
+    pub async fn remove_user_from_bitbucket_groups(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        groups: &Vec<models::bitbucket::Group>,
+    ) -> Result<bool, reqwest::Error> {
+        let mut removed = true;
+        for group in groups {
+            let member = match group
+                .members
+                .iter()
+                .find(|member| member.get_id() == purge.user.id)
+            {
+                Some(member) => member,
+                None => continue,
+            };
+            let response = self
+                .send(
+                    self.client
+                        .delete(self.get_bitbucket_url(&format!(
+                            "/1.0/groups/{}/{}/members/{}",
+                            robot.config.credential.workspace, group.slug, member.uuid
+                        )))
+                        .header(reqwest::header::AUTHORIZATION, self.authorization(robot)),
+                )
+                .await?;
+            if !response.status().is_success() {
+                log::warn!(
+                    "Failed to remove user {:?} from Bitbucket group {:?} ({})",
+                    purge.user.display_name,
+                    group.name,
+                    response.status()
+                );
+                removed = false;
+            }
+        }
+        return Ok(removed);
+    }
+
+    pub async fn purge_bitbucket_user(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        action: &models::purge::PurgeAction,
+    ) -> Result<bool, reqwest::Error> {
+        if *action == models::purge::PurgeAction::Deactivate {
+            return self.deactivate_user_in_organization(robot, purge).await;
+        }
+
+        // Workspace access comes from groups, so leaving all of them removes the user
+        let groups = self
+            .get_bitbucket_groups(robot)
+            .await?
+            .into_iter()
+            .filter(|group| match action {
+                models::purge::PurgeAction::RemoveFromGroups { groups } => groups
+                    .iter()
+                    .any(|name| group.name == *name || group.slug == *name),
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        return self
+            .remove_user_from_bitbucket_groups(robot, purge, &groups)
+            .await;
+    }
+
+    pub async fn post_webhook(
+        &self,
+        url: &String,
+        payload: &serde_json::Value,
+    ) -> Result<bool, reqwest::Error> {
+        let response = self.send(self.client.post(url).json(payload)).await?;
+        return Ok(response.status().is_success());
+    }
 }
@@ -18,7 +18,7 @@ impl Client {
                 )),
                 rusoto_core::Region::Custom {
                     name: String::from("s3-sgp1"),
-                    endpoint: String::from("https://sgp1.digitaloceanspaces.com"),
+                    endpoint: config.endpoint,
                 },
             ),
         });
@@ -0,0 +1,299 @@
+use crate::*;
+
+#[async_trait::async_trait]
+pub trait UserSource: Send + Sync {
+    fn client(&self) -> &clients::reqwest::Client;
+
+    async fn get_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error>;
+
+    async fn get_group_memberships(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<models::jira::GroupMemberships, reqwest::Error> {
+        return self.client().get_jira_group_memberships(robot).await;
+    }
+
+    async fn get_role_holders(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Option<std::collections::HashSet<String>>, reqwest::Error> {
+        return Ok(Some(
+            self.client().get_jira_project_role_holders(robot).await?,
+        ));
+    }
+
+    async fn get_activity(
+        &self,
+        robot: &models::robot::Robot,
+        user_id: &String,
+        days: i64,
+    ) -> Result<i64, reqwest::Error> {
+        return self
+            .client()
+            .get_jira_issue_activity(robot, user_id, days)
+            .await;
+    }
+
+    async fn purge_user(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        action: &models::purge::PurgeAction,
+    ) -> Result<bool, reqwest::Error> {
+        return self.client().purge_user(robot, purge, action).await;
+    }
+}
+
+pub struct AdminHub<'a> {
+    client: &'a clients::reqwest::Client,
+}
+
+pub struct Organization<'a> {
+    client: &'a clients::reqwest::Client,
+}
+
+pub struct DataCenter<'a> {
+    client: &'a clients::reqwest::Client,
+}
+
+pub struct Confluence<'a> {
+    client: &'a clients::reqwest::Client,
+    directory: Option<Box<dyn UserSource + 'a>>,
+}
+
+pub struct Bitbucket<'a> {
+    client: &'a clients::reqwest::Client,
+    directory: Option<Box<dyn UserSource + 'a>>,
+}
+
+#[async_trait::async_trait]
+impl<'a> UserSource for AdminHub<'a> {
+    fn client(&self) -> &clients::reqwest::Client {
+        return self.client;
+    }
+
+    async fn get_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        return self
+            .client
+            .get_jira_users(&robot.config.credential.cloud_session_token)
+            .await;
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> UserSource for Organization<'a> {
+    fn client(&self) -> &clients::reqwest::Client {
+        return self.client;
+    }
+
+    async fn get_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        return self.client.get_organization_users(robot).await;
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> UserSource for DataCenter<'a> {
+    fn client(&self) -> &clients::reqwest::Client {
+        return self.client;
+    }
+
+    async fn get_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        return self.client.get_data_center_users(robot).await;
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> UserSource for Confluence<'a> {
+    fn client(&self) -> &clients::reqwest::Client {
+        return self.client;
+    }
+
+    async fn get_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        let members = self.client.get_confluence_users(robot).await?;
+        return with_directory(robot, &self.directory, members, "confluence").await;
+    }
+
+    async fn get_group_memberships(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<models::jira::GroupMemberships, reqwest::Error> {
+        return self.client.get_confluence_group_memberships(robot).await;
+    }
+
+    async fn get_role_holders(
+        &self,
+        _robot: &models::robot::Robot,
+    ) -> Result<Option<std::collections::HashSet<String>>, reqwest::Error> {
+        return Ok(None);
+    }
+
+    async fn get_activity(
+        &self,
+        robot: &models::robot::Robot,
+        user_id: &String,
+        days: i64,
+    ) -> Result<i64, reqwest::Error> {
+        return self
+            .client
+            .get_confluence_activity(robot, user_id, days)
+            .await;
+    }
+
+    async fn purge_user(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        action: &models::purge::PurgeAction,
+    ) -> Result<bool, reqwest::Error> {
+        return self
+            .client
+            .purge_confluence_user(robot, purge, action)
+            .await;
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> UserSource for Bitbucket<'a> {
+    fn client(&self) -> &clients::reqwest::Client {
+        return self.client;
+    }
+
+    async fn get_users(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+        let members = self.client.get_bitbucket_members(robot).await?;
+        return with_directory(robot, &self.directory, members, "bitbucket").await;
+    }
+
+    async fn get_group_memberships(
+        &self,
+        robot: &models::robot::Robot,
+    ) -> Result<models::jira::GroupMemberships, reqwest::Error> {
+        return self.client.get_bitbucket_group_memberships(robot).await;
+    }
+
+    async fn get_role_holders(
+        &self,
+        _robot: &models::robot::Robot,
+    ) -> Result<Option<std::collections::HashSet<String>>, reqwest::Error> {
+        return Ok(None);
+    }
+
+    async fn get_activity(
+        &self,
+        robot: &models::robot::Robot,
+        user_id: &String,
+        days: i64,
+    ) -> Result<i64, reqwest::Error> {
+        return self
+            .client
+            .get_bitbucket_activity(robot, user_id, days)
+            .await;
+    }
+
+    async fn purge_user(
+        &self,
+        robot: &models::robot::Robot,
+        purge: &models::purge::PurgeData,
+        action: &models::purge::PurgeAction,
+    ) -> Result<bool, reqwest::Error> {
+        return self.client.purge_bitbucket_user(robot, purge, action).await;
+    }
+}
+
+async fn with_directory<'a>(
+    robot: &models::robot::Robot,
+    directory: &Option<Box<dyn UserSource + 'a>>,
+    members: Vec<models::jira::User>,
+    product: &str,
+) -> Result<Vec<models::jira::User>, errors::atlassian::Error> {
+    let directory = match directory {
+        Some(directory) => directory.get_users(robot).await?,
+        None => return Ok(members),
+    };
+    let directory = directory
+        .into_iter()
+        .map(|user| (user.id.clone(), user))
+        .collect::<std::collections::HashMap<_, _>>();
+
+    // Product members take their presence, email and admin roles from the organization directory
+    return Ok(members
+        .into_iter()
+        .map(|member| match directory.get(&member.id) {
+            Some(user) => {
+                let mut user = user.clone();
+                let product_presence = user.product_presence.as_ref().and_then(|presence| {
+                    return presence
+                        .iter()
+                        .filter(|(key, _time)| key.contains(product))
+                        .map(|(_key, time)| *time)
+                        .max();
+                });
+                if product_presence.is_some() {
+                    user.presence = product_presence;
+                }
+                user.system = user.system || member.system;
+                user
+            }
+            None => member,
+        })
+        .collect());
+}
+
+fn get_directory<'a>(
+    client: &'a clients::reqwest::Client,
+    robot: &models::robot::Robot,
+) -> Option<Box<dyn UserSource + 'a>> {
+    // The directory is optional for Confluence and Bitbucket, without it users have no presence
+    return match robot.config.credential.user_source {
+        models::robot::RobotSource::AdminHub
+            if !robot.config.credential.cloud_session_token.is_empty() =>
+        {
+            Some(Box::new(AdminHub { client }))
+        }
+        models::robot::RobotSource::Organization
+            if !robot.config.credential.admin_api_key.is_empty() =>
+        {
+            Some(Box::new(Organization { client }))
+        }
+        _ => None,
+    };
+}
+
+pub fn new<'a>(
+    client: &'a clients::reqwest::Client,
+    robot: &models::robot::Robot,
+) -> Box<dyn UserSource + 'a> {
+    return match robot.config.credential.platform_type {
+        // Data Center lists its users itself, the user source only applies to Cloud
+        models::robot::RobotPlatform::JiraDataCenter => Box::new(DataCenter { client }),
+        models::robot::RobotPlatform::ConfluenceCloud => Box::new(Confluence {
+            client,
+            directory: get_directory(client, robot),
+        }),
+        models::robot::RobotPlatform::BitbucketCloud => Box::new(Bitbucket {
+            client,
+            directory: get_directory(client, robot),
+        }),
+        models::robot::RobotPlatform::JiraCloud => match robot.config.credential.user_source {
+            models::robot::RobotSource::AdminHub => Box::new(AdminHub { client }),
+            models::robot::RobotSource::Organization => Box::new(Organization { client }),
+        },
+    };
+}
@@ -1,6 +1,8 @@
 pub struct Config {
     pub username: String,
     pub password: String,
+    pub uri: Option<String>,
+    pub database: String,
 }
 
 impl Config {
@@ -8,6 +10,8 @@ impl Config {
         return Ok(Self {
             username: std::env::var("MONGODB_USERNAME")?,
             password: std::env::var("MONGODB_PASSWORD")?,
+            uri: std::env::var("MONGODB_URI").ok(),
+            database: std::env::var("MONGODB_DATABASE").unwrap_or("robots".to_string()),
         });
     }
 }
@@ -1,6 +1,12 @@
+#[derive(Clone)]
 pub struct Config {
     pub email: String,
     pub password: String,
+    pub webhook: Option<String>,
+    pub public_url: Option<String>,
+    pub keep_secret: Option<String>,
+    pub keep_exemption: chrono::Duration,
+    pub managers_dir: Option<String>,
 }
 
 impl Config {
@@ -8,6 +14,17 @@ impl Config {
         return Ok(Self {
             email: std::env::var("NOTIFICATION_EMAIL")?,
             password: std::env::var("NOTIFICATION_PASSWORD")?,
+            webhook: std::env::var("NOTIFICATION_WEBHOOK").ok(),
+            // Warning emails only carry a "keep my account" link when both are set
+            public_url: std::env::var("PUBLIC_URL").ok(),
+            keep_secret: std::env::var("KEEP_SECRET").ok(),
+            keep_exemption: chrono::Duration::days(
+                std::env::var("KEEP_EXEMPTION")
+                    .unwrap_or("90".to_string())
+                    .parse()?,
+            ),
+            // Manager files of escalating robots are only read from here
+            managers_dir: std::env::var("MANAGERS_DIR").ok(),
         });
     }
 }
@@ -1,12 +1,52 @@
 #[derive(Clone)]
 pub struct Config {
     pub organization_id: String,
+    pub timeout: u64,
+    pub retries: u32,
+    pub backoff: u64,
+    pub rate: f64,
+    pub burst: f64,
+    pub site_url: String,
+    pub admin_url: String,
+    pub api_url: String,
+    pub bitbucket_url: String,
 }
 
 impl Config {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        return Ok(Self {
+        let config = Self {
             organization_id: std::env::var("ORGANIZATION_ID")?,
-        });
+            timeout: std::env::var("REQUEST_TIMEOUT")
+                .unwrap_or("30".to_string())
+                .parse()?,
+            retries: std::env::var("REQUEST_RETRIES")
+                .unwrap_or("3".to_string())
+                .parse()?,
+            backoff: std::env::var("REQUEST_BACKOFF")
+                .unwrap_or("500".to_string())
+                .parse()?,
+            rate: std::env::var("REQUEST_RATE")
+                .unwrap_or("10".to_string())
+                .parse()?,
+            burst: std::env::var("REQUEST_BURST")
+                .unwrap_or("20".to_string())
+                .parse()?,
+            site_url: std::env::var("SITE_URL")
+                .unwrap_or("https://telkomdevelopernetwork.atlassian.net".to_string()),
+            admin_url: std::env::var("ADMIN_URL")
+                .unwrap_or("https://admin.atlassian.com".to_string()),
+            api_url: std::env::var("API_URL").unwrap_or("https://api.atlassian.com".to_string()),
+            bitbucket_url: std::env::var("BITBUCKET_URL")
+                .unwrap_or("https://api.bitbucket.org".to_string()),
+        };
+
+        // The limiter waits 1 / REQUEST_RATE seconds per request and needs a whole token to send one
+        if !config.rate.is_finite() || config.rate <= 0.0 {
+            return Err("REQUEST_RATE must be greater than 0".into());
+        }
+        if !config.burst.is_finite() || config.burst < 1.0 {
+            return Err("REQUEST_BURST must be at least 1".into());
+        }
+        return Ok(config);
     }
 }
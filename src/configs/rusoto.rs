@@ -1,6 +1,7 @@
 pub struct Config {
     pub key: String,
     pub secret: String,
+    pub endpoint: String,
 }
 
 impl Config {
@@ -8,6 +9,8 @@ impl Config {
         return Ok(Self {
             key: std::env::var("AWS_ACCESS_KEY")?,
             secret: std::env::var("AWS_SECRET_KEY")?,
+            endpoint: std::env::var("S3_ENDPOINT")
+                .unwrap_or("https://sgp1.digitaloceanspaces.com".to_string()),
         });
     }
 }
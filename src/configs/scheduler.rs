@@ -1,11 +1,47 @@
+#[derive(Clone)]
 pub struct Config {
     pub schedule: cron::Schedule,
+    pub instance_id: String,
+    pub lease: chrono::Duration,
+    pub leader_election: bool,
+    pub purge_attempts: i64,
+    pub shutdown_deadline: chrono::Duration,
+    pub workers: usize,
+    pub robot_timeout: chrono::Duration,
 }
 
 impl Config {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
         return Ok(Self {
             schedule: std::env::var("SCHEDULE")?.parse()?,
+            // Replicas tell their locks apart by this id, the hostname is unique per container
+            instance_id: std::env::var("INSTANCE_ID")
+                .or(std::env::var("HOSTNAME"))
+                .unwrap_or(format!("{:016x}", rand::random::<u64>())),
+            lease: chrono::Duration::seconds(
+                std::env::var("LOCK_LEASE")
+                    .unwrap_or("60".to_string())
+                    .parse()?,
+            ),
+            leader_election: std::env::var("LEADER_ELECTION")
+                .unwrap_or("false".to_string())
+                .parse()?,
+            purge_attempts: std::env::var("PURGE_ATTEMPTS")
+                .unwrap_or("3".to_string())
+                .parse()?,
+            shutdown_deadline: chrono::Duration::seconds(
+                std::env::var("SHUTDOWN_DEADLINE")
+                    .unwrap_or("30".to_string())
+                    .parse()?,
+            ),
+            workers: std::env::var("SCHEDULER_WORKERS")
+                .unwrap_or("4".to_string())
+                .parse()?,
+            robot_timeout: chrono::Duration::seconds(
+                std::env::var("ROBOT_TIMEOUT")
+                    .unwrap_or("3600".to_string())
+                    .parse()?,
+            ),
         });
     }
 }
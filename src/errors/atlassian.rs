@@ -0,0 +1,64 @@
+#[derive(Debug)]
+pub enum Error {
+    Unauthorized,
+    RateLimited {
+        retry_after: Option<u64>,
+    },
+    Status(reqwest::StatusCode),
+    Request(reqwest::Error),
+    Schema(serde_json::Error),
+    Partial {
+        fetched: usize,
+        total: Option<i32>,
+        cause: Option<Box<Error>>,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, format: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            Error::Unauthorized => write!(format, "Atlassian rejected the credentials"),
+            Error::RateLimited { retry_after } => match retry_after {
+                Some(retry_after) => write!(
+                    format,
+                    "Atlassian rate limit reached, retry after {} seconds",
+                    retry_after
+                ),
+                None => write!(format, "Atlassian rate limit reached"),
+            },
+            Error::Status(status) => write!(format, "Atlassian responded with {}", status),
+            Error::Request(error) => write!(format, "Request to Atlassian failed ({})", error),
+            Error::Schema(error) => write!(
+                format,
+                "Atlassian response doesn't match the expected schema ({})",
+                error
+            ),
+            Error::Partial {
+                fetched,
+                total,
+                cause,
+            } => {
+                match total {
+                    Some(total) => write!(
+                        format,
+                        "Only {} of {} users could be retrieved",
+                        fetched, total
+                    )?,
+                    None => write!(format, "Only {} users could be retrieved", fetched)?,
+                }
+                match cause {
+                    Some(cause) => write!(format, " ({})", cause),
+                    None => Ok(()),
+                }
+            }
+        };
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::convert::From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        return Error::Request(error);
+    }
+}
@@ -1,2 +1,3 @@
+pub mod atlassian;
 pub mod error;
-pub mod handler;
\ No newline at end of file
+pub mod handler;
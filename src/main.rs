@@ -26,25 +26,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mongodb_client = clients::mongodb::Client::new(mongodb_config).await?;
     let rusoto_client = clients::rusoto::Client::new(rusoto_config)?;
 
-    // Run scheduler.
-    let (scheduler_exit_sender, scheduler_exit_receiver) = tokio::sync::mpsc::channel(1);
+    // Run scheduler, changes to robots wake it up to reschedule.
+    let (scheduler_shutdown_sender, scheduler_shutdown_receiver) =
+        tokio::sync::watch::channel(false);
+    let scheduler_shutdown_sender = std::sync::Arc::new(scheduler_shutdown_sender);
+    let scheduler_wake = std::sync::Arc::new(tokio::sync::Notify::new());
     let scheduler_handle = actix_rt::spawn(utils::scheduler::run(
-        scheduler_exit_receiver,
-        scheduler_config,
-        notification_config,
+        scheduler_shutdown_receiver,
+        scheduler_wake.clone(),
+        scheduler_config.clone(),
+        notification_config.clone(),
         reqwest_client.clone(),
         mongodb_client.clone(),
         rusoto_client.clone(),
     ));
 
     // Run server.
-    actix_web::HttpServer::new(move || {
+    let server = actix_web::HttpServer::new(move || {
         actix_web::App::new()
             .wrap(actix_cors::Cors::permissive())
             .wrap(actix_web::middleware::Logger::default())
             .app_data(actix_web::web::Data::new(reqwest_client.clone()))
             .app_data(actix_web::web::Data::new(mongodb_client.clone()))
             .app_data(actix_web::web::Data::new(rusoto_client.clone()))
+            .app_data(actix_web::web::Data::new(scheduler_config.clone()))
+            .app_data(actix_web::web::Data::new(notification_config.clone()))
+            .app_data(actix_web::web::Data::from(scheduler_wake.clone()))
             .app_data(actix_web::web::JsonConfig::default().error_handler(errors::handler::json))
             .app_data(actix_web::web::QueryConfig::default().error_handler(errors::handler::query))
             .service(
@@ -55,6 +62,37 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .route(actix_web::web::delete().to(routes::robots::delete))
                     .route(actix_web::web::to(errors::handler::method_not_allowed)),
             )
+            .service(
+                actix_web::web::resource("/robots/resume")
+                    .route(actix_web::web::post().to(routes::robots::resume))
+                    .route(actix_web::web::to(errors::handler::method_not_allowed)),
+            )
+            .service(
+                actix_web::web::resource("/robots/{id}/users")
+                    .route(actix_web::web::get().to(routes::users::get))
+                    .route(actix_web::web::to(errors::handler::method_not_allowed)),
+            )
+            .service(
+                actix_web::web::resource("/robots/{id}/forecast")
+                    .route(actix_web::web::get().to(routes::forecast::get))
+                    .route(actix_web::web::to(errors::handler::method_not_allowed)),
+            )
+            .service(
+                actix_web::web::resource("/purge/approve")
+                    .route(actix_web::web::post().to(routes::purge::approve))
+                    .route(actix_web::web::to(errors::handler::method_not_allowed)),
+            )
+            .service(
+                actix_web::web::resource("/purge/retry")
+                    .route(actix_web::web::post().to(routes::purge::retry))
+                    .route(actix_web::web::to(errors::handler::method_not_allowed)),
+            )
+            .service(
+                actix_web::web::resource("/purge/keep")
+                    .route(actix_web::web::get().to(routes::purge::confirm_keep))
+                    .route(actix_web::web::post().to(routes::purge::keep))
+                    .route(actix_web::web::to(errors::handler::method_not_allowed)),
+            )
             .service(
                 actix_web::web::resource("/report")
                     .route(actix_web::web::get().to(routes::report::get))
@@ -63,11 +101,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .default_service(actix_web::web::route().to(errors::handler::not_found))
     })
     .bind((server_config.address, server_config.port))?
-    .run()
-    .await?;
+    .disable_signals()
+    .run();
+
+    // Stop the scheduler as soon as a shutdown is requested instead of after the server is done.
+    let server_handle = server.handle();
+    let shutdown_sender = scheduler_shutdown_sender.clone();
+    actix_rt::spawn(async move {
+        utils::shutdown::wait().await;
+        log::info!("shutdown requested");
+        shutdown_sender.send_replace(true);
+        server_handle.stop(true).await;
+    });
+    server.await?;
 
-    // Stop scheduler.
-    scheduler_exit_sender.send(()).await.unwrap();
+    // Stop scheduler, robots past the deadline are abandoned and reported by the scheduler.
+    scheduler_shutdown_sender.send_replace(true);
     scheduler_handle.await.unwrap();
 
     return Ok(());
@@ -0,0 +1,60 @@
+use crate::*;
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct User {
+    #[serde(rename = "type")]
+    pub user_type: String,
+    pub account_id: Option<String>,
+    pub uuid: String,
+    pub display_name: String,
+    pub nickname: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Membership {
+    pub user: User,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Memberships {
+    pub values: Vec<Membership>,
+    pub next: Option<String>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PullRequests {
+    pub size: Option<i64>,
+    pub values: Vec<serde_json::Value>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub slug: String,
+    #[serde(default)]
+    pub members: Vec<User>,
+}
+
+impl User {
+    pub fn get_id(&self) -> String {
+        // App users have no account id, so they fall back to their uuid
+        return self.account_id.clone().unwrap_or_else(|| self.uuid.clone());
+    }
+
+    pub fn to_user(&self) -> models::jira::User {
+        return models::jira::User {
+            id: self.get_id(),
+            display_name: self.display_name.clone(),
+            active: true,
+            active_status: "active".to_string(),
+            nickname: self.nickname.clone().unwrap_or_default(),
+            // Bitbucket doesn't expose creation dates, emails or presence
+            created: None,
+            system: self.user_type != "user",
+            ..Default::default()
+        };
+    }
+}
@@ -0,0 +1,63 @@
+use crate::*;
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub account_id: String,
+    pub account_type: String,
+    pub display_name: String,
+    pub email: Option<String>,
+    pub time_zone: Option<String>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SearchResult {
+    pub user: Option<User>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Search {
+    pub results: Vec<SearchResult>,
+    pub size: i64,
+    pub total_size: i64,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Members {
+    pub results: Vec<User>,
+    pub size: i64,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Group {
+    pub name: String,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Groups {
+    pub results: Vec<Group>,
+    pub size: i64,
+}
+
+impl User {
+    pub fn to_user(&self) -> models::jira::User {
+        return models::jira::User {
+            id: self.account_id.clone(),
+            email: self.email.clone().unwrap_or_default(),
+            display_name: self.display_name.clone(),
+            active: true,
+            has_verified_email: true,
+            active_status: "active".to_string(),
+            nickname: self.display_name.clone(),
+            timezone: self.time_zone.clone(),
+            // Confluence doesn't expose creation dates or presence
+            created: None,
+            system: self.account_type != "atlassian",
+            ..Default::default()
+        };
+    }
+}
@@ -0,0 +1,250 @@
+use crate::*;
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ForecastQuery {
+    pub days: Option<i64>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastUser {
+    #[serde(flatten)]
+    pub user: models::jira::User,
+    pub effective_presence: chrono::DateTime<chrono::Utc>,
+    pub eligible: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ForecastDay {
+    pub date: chrono::NaiveDate,
+    pub count: usize,
+}
+
+#[derive(PartialEq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Forecast {
+    pub robot: mongodb::bson::oid::ObjectId,
+    pub fetched: chrono::DateTime<chrono::Utc>,
+    pub last_active: i64,
+    pub days: i64,
+    pub users: Vec<ForecastUser>,
+    pub daily: Vec<ForecastDay>,
+    pub approximations: Vec<String>,
+}
+
+impl Forecast {
+    pub fn new(
+        robot: &models::robot::Robot,
+        snapshot: &models::snapshot::Snapshot,
+        users: Vec<models::snapshot::SnapshotUser>,
+        memberships: &models::jira::GroupMemberships,
+        exemptions: &[models::purge::PurgeExemption],
+        days: i64,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        let last_active = chrono::Duration::days(robot.config.scheduler.last_active);
+        let until = now + chrono::Duration::days(days);
+
+        let mut forecast_users = users
+            .into_iter()
+            .map(|snapshot| snapshot.user)
+            // Never purged, outside the robot's groups or already purged for being inactive
+            .filter(|user| !user.system && robot.is_user_purgeable(&user.id, memberships))
+            .filter(|user| !robot.config.scheduler.check_active_status || user.active)
+            .filter_map(|user| {
+                // Users without a known presence never become inactive
+                let effective_presence = user.get_available_presence()?;
+
+                // Users who kept their account aren't queued again before their exemption ends
+                let eligible = exemptions
+                    .iter()
+                    .filter(|exemption| exemption.user.id == user.id)
+                    .map(|exemption| exemption.until)
+                    .fold(effective_presence + last_active, std::cmp::max);
+                if eligible <= now || eligible > until {
+                    return None;
+                }
+                return Some(ForecastUser {
+                    user,
+                    effective_presence,
+                    eligible,
+                });
+            })
+            .collect::<Vec<_>>();
+        forecast_users.sort_by_key(|user| user.eligible);
+
+        let daily = (1..=days)
+            .map(|day| {
+                let from = now + chrono::Duration::days(day - 1);
+                let to = now + chrono::Duration::days(day);
+                return ForecastDay {
+                    date: to.date_naive(),
+                    count: forecast_users
+                        .iter()
+                        .filter(|user| user.eligible > from && user.eligible <= to)
+                        .count(),
+                };
+            })
+            .collect();
+
+        return Self {
+            robot: snapshot.robot,
+            fetched: snapshot.fetched,
+            last_active: robot.config.scheduler.last_active,
+            days,
+            users: forecast_users,
+            daily,
+            approximations: Self::get_approximations(robot),
+        };
+    }
+
+    fn get_approximations(robot: &models::robot::Robot) -> Vec<String> {
+        // The forecast only replays the inactivity check, everything else the scheduler does is listed
+        let mut approximations = vec![
+            "Presence is read from the last snapshot, logins since then aren't known".to_string(),
+        ];
+        if robot.config.scheduler.check_project_role {
+            approximations.push("Project role holders aren't excluded".to_string());
+        }
+        if robot.config.scheduler.issue_activity > 0 {
+            approximations.push("Recent issue activity isn't checked".to_string());
+        }
+        if !robot.config.scheduler.rules.is_empty() {
+            approximations.push("Custom rules aren't applied".to_string());
+        }
+        if !robot.config.scheduler.purge_admins {
+            approximations
+                .push("Admins are counted, though their removal needs approval".to_string());
+        }
+        return approximations;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn get_user(
+        id: &str,
+        presence: chrono::DateTime<chrono::Utc>,
+    ) -> models::snapshot::SnapshotUser {
+        return models::snapshot::SnapshotUser {
+            user: models::jira::User {
+                id: id.to_string(),
+                active: true,
+                presence: Some(presence),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+    }
+
+    fn get_robot() -> models::robot::Robot {
+        let mut robot = models::robot::Robot::default();
+        robot.config.scheduler.last_active = 90;
+        robot.config.scheduler.purge_admins = true;
+        return robot;
+    }
+
+    #[test]
+    fn forecast_buckets_users_by_day() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 1, 12, 0, 0).unwrap();
+        let users = vec![
+            // Already inactive, so already queued or about to be
+            get_user("alice", now - chrono::Duration::days(91)),
+            // Inactive half a day from now, on the first day
+            get_user(
+                "bob",
+                now - chrono::Duration::days(90) + chrono::Duration::hours(12),
+            ),
+            // Inactive exactly at the end of the first day
+            get_user("carol", now - chrono::Duration::days(89)),
+            // Inactive early on the second day
+            get_user(
+                "dave",
+                now - chrono::Duration::days(89) + chrono::Duration::hours(1),
+            ),
+            // Past the forecast
+            get_user("erin", now - chrono::Duration::days(80)),
+        ];
+
+        let forecast = models::forecast::Forecast::new(
+            &get_robot(),
+            &models::snapshot::Snapshot::default(),
+            users,
+            &models::jira::GroupMemberships::new(),
+            &[],
+            3,
+            now,
+        );
+        assert_eq!(
+            forecast
+                .users
+                .iter()
+                .map(|user| user.user.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["bob", "carol", "dave"]
+        );
+        assert_eq!(
+            forecast.daily,
+            vec![
+                models::forecast::ForecastDay {
+                    date: chrono::NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(),
+                    count: 2,
+                },
+                models::forecast::ForecastDay {
+                    date: chrono::NaiveDate::from_ymd_opt(2023, 1, 3).unwrap(),
+                    count: 1,
+                },
+                models::forecast::ForecastDay {
+                    date: chrono::NaiveDate::from_ymd_opt(2023, 1, 4).unwrap(),
+                    count: 0,
+                },
+            ]
+        );
+        assert_eq!(forecast.approximations.len(), 1);
+    }
+
+    #[test]
+    fn forecast_applies_exclusions() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 1, 12, 0, 0).unwrap();
+        let presence = now - chrono::Duration::days(89);
+        let mut robot = get_robot();
+        robot.config.scheduler.exclude_groups = vec!["staff".to_string()];
+        let memberships = models::jira::GroupMemberships::from([(
+            "staff".to_string(),
+            std::collections::HashSet::from(["bob".to_string()]),
+        )]);
+
+        // Carol kept their account for another five days
+        let mut exemption = models::purge::PurgeExemption::default();
+        exemption.user.id = "carol".to_string();
+        exemption.until = now + chrono::Duration::days(5);
+
+        let forecast = models::forecast::Forecast::new(
+            &robot,
+            &models::snapshot::Snapshot::default(),
+            vec![
+                get_user("alice", presence),
+                get_user("bob", presence),
+                get_user("carol", presence),
+            ],
+            &memberships,
+            &[exemption],
+            30,
+            now,
+        );
+        assert_eq!(
+            forecast
+                .users
+                .iter()
+                .map(|user| (user.user.id.as_str(), user.eligible))
+                .collect::<Vec<_>>(),
+            vec![
+                ("alice", now + chrono::Duration::days(1)),
+                ("carol", now + chrono::Duration::days(5)),
+            ]
+        );
+    }
+}
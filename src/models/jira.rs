@@ -15,6 +15,23 @@ pub struct ProjectRoleUser {
     pub account_id: String,
 }
 
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct ProjectRoleGroup {
+    pub name: String,
+}
+
+#[serde_with::skip_serializing_none]
 #[derive(
     PartialEq,
     Eq,
@@ -30,7 +47,11 @@ pub struct ProjectRoleUser {
 #[serde(rename_all = "camelCase")]
 pub struct RoleActor {
     pub display_name: String,
-    pub actor_user: ProjectRoleUser,
+    pub actor_user: Option<ProjectRoleUser>,
+    pub actor_group: Option<ProjectRoleGroup>,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub actor_type: Option<String>,
 }
 
 #[derive(
@@ -139,7 +160,7 @@ pub struct User {
     pub company_name: Option<String>,
     pub department: Option<String>,
     pub presence: Option<chrono::DateTime<chrono::Utc>>,
-    pub created: chrono::DateTime<chrono::Utc>,
+    pub created: Option<chrono::DateTime<chrono::Utc>>,
     pub invitation_status: Option<InvitationStatus>,
     pub system: bool,
     pub org_admin: bool,
@@ -148,17 +169,34 @@ pub struct User {
     pub trusted_user: bool,
     pub presence_unavailable: Option<bool>,
     pub managed_status: ManagedStatus,
+    pub product_presence: Option<std::collections::BTreeMap<String, chrono::DateTime<chrono::Utc>>>,
 }
 
 impl User {
-    pub fn get_available_presence(&self) -> chrono::DateTime<chrono::Utc> {
-        return self.presence.unwrap_or_else(|| {
-            if self.invitation_status.is_some() {
-                return self.invitation_status.as_ref().unwrap().invited_at;
+    pub fn get_available_presence(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        // Users with neither activity, an invitation nor a known creation date count as new rather than inactive
+        return self.presence.or_else(|| {
+            if let Some(invitation_status) = &self.invitation_status {
+                return Some(invitation_status.invited_at);
             }
             return self.created;
         });
     }
+
+    pub fn is_privileged(&self) -> bool {
+        return self.org_admin || self.site_admin || self.sys_admin || self.trusted_user;
+    }
+
+    pub fn get_pending_invitation(&self) -> Option<&InvitationStatus> {
+        // An invitation stays pending until the user logs in for the first time, unless it was accepted or revoked
+        if self.presence.is_some() {
+            return None;
+        }
+        return self.invitation_status.as_ref().filter(|invitation| {
+            return invitation.status.eq_ignore_ascii_case("pending")
+                || invitation.status.eq_ignore_ascii_case("expired");
+        });
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
@@ -166,3 +204,171 @@ pub struct Users {
     pub users: Vec<User>,
     pub total: i32,
 }
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationRole {
+    pub key: String,
+    pub groups: Vec<String>,
+    pub default_groups: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMember {
+    // Data Center identifies members by username
+    #[serde(alias = "name")]
+    pub account_id: String,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMembers {
+    pub values: Vec<GroupMember>,
+    pub is_last: bool,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GroupName {
+    pub name: String,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GroupNames {
+    pub items: Vec<GroupName>,
+}
+
+// Data Center lists a user's groups by expanding the user
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct UserGroups {
+    pub groups: GroupNames,
+}
+
+pub type GroupMemberships = std::collections::HashMap<String, std::collections::HashSet<String>>;
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Search {
+    pub total: i64,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OrganizationProductAccess {
+    pub key: String,
+    pub last_active: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OrganizationUser {
+    pub account_id: String,
+    pub account_type: String,
+    pub account_status: String,
+    pub name: String,
+    pub picture: Option<String>,
+    pub email: Option<String>,
+    pub last_active: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub product_access: Vec<OrganizationProductAccess>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OrganizationLinks {
+    pub next: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OrganizationUsers {
+    pub data: Vec<OrganizationUser>,
+    pub links: OrganizationLinks,
+}
+
+impl OrganizationUser {
+    pub fn to_user(&self) -> User {
+        let product_presence = self
+            .product_access
+            .iter()
+            .filter_map(|product| Some((product.key.clone(), product.last_active?)))
+            .collect::<std::collections::BTreeMap<_, _>>();
+
+        return User {
+            id: self.account_id.clone(),
+            email: self.email.clone().unwrap_or_default(),
+            display_name: self.name.clone(),
+            active: self.account_status == "active",
+            has_verified_email: true,
+            picture: self.picture.clone().unwrap_or_default(),
+            active_status: self.account_status.clone(),
+            nickname: self.name.clone(),
+            // The most recent activity in any product counts as presence
+            presence: self
+                .last_active
+                .into_iter()
+                .chain(product_presence.values().copied())
+                .max(),
+            // The API doesn't expose creation dates
+            created: None,
+            system: self.account_type != "atlassian",
+            product_presence: Some(product_presence),
+            ..Default::default()
+        };
+    }
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataCenterUser {
+    pub key: String,
+    pub name: String,
+    pub email_address: Option<String>,
+    pub display_name: String,
+    pub active: bool,
+    pub time_zone: Option<String>,
+    // Missing when the instance doesn't report last logins, null for users who never logged in
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub last_login_time: Option<Option<String>>,
+}
+
+impl DataCenterUser {
+    pub fn to_user(&self, last_login: Option<chrono::DateTime<chrono::Utc>>) -> User {
+        return User {
+            // Every Data Center endpoint takes the username rather than the key
+            id: self.name.clone(),
+            email: self.email_address.clone().unwrap_or_default(),
+            display_name: self.display_name.clone(),
+            active: self.active,
+            has_verified_email: true,
+            active_status: if self.active { "active" } else { "inactive" }.to_string(),
+            nickname: self.name.clone(),
+            timezone: self.time_zone.clone(),
+            presence: last_login,
+            // Data Center doesn't expose creation dates
+            created: None,
+            ..Default::default()
+        };
+    }
+
+    pub fn get_last_login(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>, String> {
+        let last_login_time = self
+            .last_login_time
+            .as_ref()
+            .ok_or(format!("user {:?} has no 'lastLoginTime'", self.name))?;
+
+        // Jira writes offsets without a colon, e.g. "2023-01-02T10:00:00.000+0000"
+        return match last_login_time {
+            Some(last_login_time) => {
+                chrono::DateTime::parse_from_str(last_login_time, "%Y-%m-%dT%H:%M:%S%.f%z")
+                    .map(|last_login| Some(last_login.with_timezone(&chrono::Utc)))
+                    .map_err(|error| {
+                        format!(
+                            "user {:?} has an invalid 'lastLoginTime' ({})",
+                            self.name, error
+                        )
+                    })
+            }
+            None => Ok(None),
+        };
+    }
+}
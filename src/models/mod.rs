@@ -1,4 +1,9 @@
+pub mod bitbucket;
+pub mod confluence;
+pub mod forecast;
 pub mod jira;
+pub mod policy;
 pub mod purge;
 pub mod report;
 pub mod robot;
+pub mod snapshot;
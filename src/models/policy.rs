@@ -0,0 +1,62 @@
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum PolicyTextField {
+    DisplayName,
+    Email,
+    Title,
+    Department,
+    CompanyName,
+    Location,
+    Timezone,
+    ManagedOwner,
+}
+
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum PolicyFlag {
+    Active,
+    HasVerifiedEmail,
+    Managed,
+    OrgAdmin,
+    SiteAdmin,
+    SysAdmin,
+    TrustedUser,
+    System,
+}
+
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum PolicyCondition {
+    All(Vec<PolicyCondition>),
+    Any(Vec<PolicyCondition>),
+    Not(Box<PolicyCondition>),
+    Equals {
+        field: PolicyTextField,
+        value: String,
+    },
+    Contains {
+        field: PolicyTextField,
+        value: String,
+    },
+    IsEmpty {
+        field: PolicyTextField,
+    },
+    IsTrue {
+        flag: PolicyFlag,
+    },
+    PresenceOlderThan(i64),
+}
+
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
+pub struct PolicyRule {
+    pub name: String,
+    pub condition: PolicyCondition,
+}
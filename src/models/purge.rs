@@ -6,7 +6,6 @@ use crate::*;
     PartialOrd,
     Ord,
     Clone,
-    Copy,
     Hash,
     Default,
     Debug,
@@ -20,6 +19,115 @@ pub enum PurgeReason {
     LastActive,
     DuplicateEmail,
     DuplicateName,
+    NoProjectRole,
+    StaleInvitation,
+    Custom(String),
+}
+
+impl std::fmt::Display for PurgeReason {
+    fn fmt(&self, format: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Matches the serialized names, custom reasons show their rule's text
+        return match self {
+            PurgeReason::ActiveStatus => write!(format, "ACTIVE_STATUS"),
+            PurgeReason::LastActive => write!(format, "LAST_ACTIVE"),
+            PurgeReason::DuplicateEmail => write!(format, "DUPLICATE_EMAIL"),
+            PurgeReason::DuplicateName => write!(format, "DUPLICATE_NAME"),
+            PurgeReason::NoProjectRole => write!(format, "NO_PROJECT_ROLE"),
+            PurgeReason::StaleInvitation => write!(format, "STALE_INVITATION"),
+            PurgeReason::Custom(reason) => write!(format, "{}", reason),
+        };
+    }
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PurgeProtection {
+    #[default]
+    SystemAccount,
+    OptedIn,
+    AwaitingApproval,
+    Approved,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PurgeState {
+    #[default]
+    Queued,
+    Warned,
+    Removing,
+    Removed,
+    Failed,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PurgeOutcome {
+    #[default]
+    Removed,
+    // The user left the site after a failed removal, so it's unknown who removed them
+    Unconfirmed,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PurgeAction {
+    RevokeProduct {
+        product: String,
+    },
+    RemoveFromGroups {
+        groups: Vec<String>,
+    },
+    Deactivate,
+    #[default]
+    Delete,
 }
 
 #[derive(
@@ -39,7 +147,24 @@ pub struct PurgeUser {
     pub id: String,
     pub display_name: String,
     pub email: String,
-    pub presence: chrono::DateTime<chrono::Utc>,
+    pub presence: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct PurgeEscalation {
+    pub recipients: Vec<String>,
+    pub time: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(
@@ -58,6 +183,24 @@ pub struct PurgeRobot {
     pub id: mongodb::bson::oid::ObjectId,
 }
 
+#[serde_with::skip_serializing_none]
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct PurgeIdentifier {
+    #[serde(rename = "_id")]
+    pub unique: Option<mongodb::bson::oid::ObjectId>,
+}
+
 #[serde_with::skip_serializing_none]
 #[derive(
     PartialEq,
@@ -79,14 +222,96 @@ pub struct PurgeData {
     pub time: chrono::DateTime<chrono::Utc>,
     pub alert: Option<chrono::DateTime<chrono::Utc>>,
     pub reasons: Vec<PurgeReason>,
+    #[serde(default)]
+    pub protected: bool,
+    pub approved: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub state: PurgeState,
+    pub action: Option<PurgeAction>,
+    #[serde(default)]
+    pub attempts: i64,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub reminders: i64,
+    #[serde(default)]
+    pub escalations: Vec<PurgeEscalation>,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct PurgeDecision {
+    pub user: PurgeUser,
+    pub robot: PurgeRobot,
+    pub protection: PurgeProtection,
+    pub time: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct PurgeInvitation {
+    pub user: String,
+    pub robot: PurgeRobot,
+    // When the invitation was re-sent, staleness counts from here rather than from the original invitation
+    pub time: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct PurgeExemption {
+    pub purge: mongodb::bson::oid::ObjectId,
+    pub user: PurgeUser,
+    pub robot: PurgeRobot,
+    pub reasons: Vec<PurgeReason>,
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub until: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PurgeToken {
+    pub token: String,
 }
 
 #[serde_with::skip_serializing_none]
 #[derive(PartialEq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct PurgeLog {
+    pub purge: Option<mongodb::bson::oid::ObjectId>,
     pub user: models::jira::User,
     pub robot: models::robot::Robot,
     pub reasons: Vec<PurgeReason>,
+    #[serde(default)]
+    pub action: PurgeAction,
+    #[serde(default)]
+    pub outcome: PurgeOutcome,
     pub time: chrono::DateTime<chrono::Utc>,
 }
 
@@ -94,18 +319,98 @@ impl PurgeLog {
     pub fn new(
         robot: &models::robot::Robot,
         user: &models::jira::User,
-        reasons: Vec<PurgeReason>,
+        data: &PurgeData,
+        action: PurgeAction,
         time: chrono::DateTime<chrono::Utc>,
     ) -> Self {
         return Self {
+            purge: data.id,
             user: user.clone(),
             robot: robot.clone(),
-            reasons,
+            reasons: data.reasons.clone(),
+            action,
+            outcome: PurgeOutcome::Removed,
+            time,
+        };
+    }
+
+    pub fn from_purge(
+        robot: &models::robot::Robot,
+        data: &PurgeData,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        // The user is gone from the site, so the queue entry is all that's left of them
+        let user = models::jira::User {
+            id: data.user.id.clone(),
+            display_name: data.user.display_name.clone(),
+            email: data.user.email.clone(),
+            presence: data.user.presence,
+            ..Default::default()
+        };
+        let mut log = Self::new(
+            robot,
+            &user,
+            data,
+            data.action.clone().unwrap_or_default(),
+            time,
+        );
+
+        // Only a removal that was underway or done accounts for the user being gone
+        if !matches!(data.state, PurgeState::Removing | PurgeState::Removed) {
+            log.outcome = PurgeOutcome::Unconfirmed;
+        }
+        return log;
+    }
+}
+
+impl PurgeDecision {
+    pub fn new(
+        data: &PurgeData,
+        protection: PurgeProtection,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        return Self {
+            user: data.user.clone(),
+            robot: data.robot.clone(),
+            protection,
             time,
         };
     }
 }
 
+impl PurgeInvitation {
+    pub fn new(
+        robot: &models::robot::Robot,
+        user: &models::jira::User,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        return Self {
+            user: user.id.clone(),
+            robot: PurgeRobot {
+                id: robot.data.id.unique.unwrap(),
+            },
+            time,
+        };
+    }
+}
+
+impl PurgeExemption {
+    pub fn new(
+        data: &PurgeData,
+        time: chrono::DateTime<chrono::Utc>,
+        until: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        return Self {
+            purge: data.id.unwrap(),
+            user: data.user.clone(),
+            robot: data.robot.clone(),
+            reasons: data.reasons.clone(),
+            time,
+            until,
+        };
+    }
+}
+
 impl PurgeData {
     pub fn new(
         robot: &models::robot::Robot,
@@ -127,9 +432,29 @@ impl PurgeData {
             time,
             alert: None,
             reasons,
+            protected: false,
+            approved: None,
+            state: PurgeState::Queued,
+            action: None,
+            attempts: 0,
+            error: None,
+            reminders: 0,
+            escalations: Vec::new(),
         };
     }
 
+    pub fn is_attempted(&self) -> bool {
+        return self.attempts > 0
+            || matches!(
+                self.state,
+                PurgeState::Removing | PurgeState::Removed | PurgeState::Failed
+            );
+    }
+
+    pub fn is_awaiting_approval(&self) -> bool {
+        return self.protected && self.approved.is_none();
+    }
+
     pub fn should_email_user(&self, now: chrono::DateTime<chrono::Utc>, delay: i64) -> bool {
         if self.alert.is_none() {
             return true;
@@ -137,24 +462,52 @@ impl PurgeData {
         return self.alert.unwrap() + chrono::Duration::days(delay) < now;
     }
 
+    pub fn should_escalate(&self, escalation: &Option<models::robot::RobotEscalation>) -> bool {
+        // Escalated once, when the user still hasn't answered the last of the reminders
+        return escalation.as_ref().is_some_and(|escalation| {
+            return escalation.reminders > 0
+                && self.reminders >= escalation.reminders
+                && self.escalations.is_empty();
+        });
+    }
+
     pub fn should_remove_user(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
         return self.time <= now;
     }
 
     pub fn email_user(
         &self,
-        notification_email: &String,
-        notification_password: &String,
-        contact: &String,
+        notification_email: &str,
+        notification_password: &str,
+        contact: &str,
+        keep_link: Option<String>,
     ) -> bool {
+        // Users without an email, like Bitbucket members or hidden Confluence emails, can't be warned
+        let (from, to) = match (
+            format!("Telkom Developer Network <{}>", notification_email).parse(),
+            format!("{} <{}>", self.user.display_name, self.user.email).parse(),
+        ) {
+            (Ok(from), Ok(to)) => (from, to),
+            _ => return false,
+        };
+
         let relay = lettre::SmtpTransport::relay("smtp.gmail.com")
             .unwrap()
             .credentials(lettre::transport::smtp::authentication::Credentials::new(
-                notification_email.clone(),
-                notification_password.clone(),
+                notification_email.to_string(),
+                notification_password.to_string(),
             ))
             .build();
-        
+
+        let keep_button = keep_link.map_or(String::new(), |keep_link| {
+            return format!(
+                r#"<p>If you still need your account, let us know before then and it will be kept.</p>
+                  <a href="{}" class="btn">Keep My Account</a>
+                  "#,
+                keep_link
+            );
+        });
+
         let body = format!(
             r#"
             <!DOCTYPE html>
@@ -216,7 +569,7 @@ impl PurgeData {
                     If you have any questions or concerns, please contact {}.
                   </p>
                   <p>Thank you for your understanding.</p>
-                  <a href="https://id.atlassian.com/login" class="btn">Go to Jira Login Page</a>
+                  {}<a href="https://id.atlassian.com/login" class="btn">Go to Jira Login Page</a>
                 </div>
               </body>
             </html>
@@ -224,23 +577,88 @@ impl PurgeData {
             self.user.display_name,
             self.time.format("%d-%B-%Y %H:%M:%S").to_string(),
             self.reasons,
-            contact
+            contact,
+            keep_button
         );
 
-        let message = lettre::Message::builder()
-            .from(
-                format!("Telkom Developer Network <{}>", notification_email)
-                    .parse()
-                    .unwrap(),
-            )
-            .to(format!("{} <{}>", self.user.display_name, self.user.email)
-                .parse()
-                .unwrap())
+        return match lettre::Message::builder()
+            .from(from)
+            .to(to)
             .subject("[ALERT] Jira Access Removal")
             .header(lettre::message::header::ContentType::TEXT_HTML)
             .body(body)
-            .unwrap();
+        {
+            Ok(message) => lettre::Transport::send(&relay, &message).is_ok(),
+            Err(_error) => false,
+        };
+    }
 
-        return lettre::Transport::send(&relay, &message).is_ok();
+    pub fn email_managers(
+        &self,
+        notification_email: &str,
+        notification_password: &str,
+        recipients: &[String],
+        contact: &str,
+    ) -> bool {
+        let relay = lettre::SmtpTransport::relay("smtp.gmail.com")
+            .unwrap()
+            .credentials(lettre::transport::smtp::authentication::Credentials::new(
+                notification_email.to_string(),
+                notification_password.to_string(),
+            ))
+            .build();
+
+        let body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+              <head>
+                <title>[ALERT] Jira Access Removal</title>
+              </head>
+              <body style="font-family: Arial, sans-serif; font-size: 14px; line-height: 1.5;">
+                <h1 style="font-size: 24px;">[ALERT] Jira Access Removal</h1>
+                <p>
+                  {} ({}) hasn't answered {} reminder(s) and will lose access to Jira on
+                  {}. This action has been taken due to {:?}.
+                </p>
+                <p>
+                  If they still need access, ask them to keep their account from the warning
+                  email or contact {}.
+                </p>
+              </body>
+            </html>
+            "#,
+            self.user.display_name,
+            self.user.email,
+            self.reminders,
+            self.time.format("%d-%B-%Y %H:%M:%S"),
+            self.reasons,
+            contact
+        );
+
+        let from = match format!("Telkom Developer Network <{}>", notification_email).parse() {
+            Ok(from) => from,
+            Err(_error) => return false,
+        };
+        let mut builder = lettre::Message::builder()
+            .from(from)
+            .subject("[ALERT] Jira Access Removal")
+            .header(lettre::message::header::ContentType::TEXT_HTML);
+        for recipient in recipients {
+            match recipient.parse() {
+                Ok(mailbox) => builder = builder.to(mailbox),
+                Err(_error) => {
+                    log::warn!(
+                        "Escalation recipient {:?} isn't an email address",
+                        recipient
+                    );
+                }
+            }
+        }
+
+        return match builder.body(body) {
+            Ok(message) => lettre::Transport::send(&relay, &message).is_ok(),
+            Err(_error) => false,
+        };
     }
 }
@@ -4,14 +4,20 @@ use crate::*;
 pub struct Report {
     pub queued: Vec<models::purge::PurgeData>,
     pub removed: Vec<models::purge::PurgeLog>,
+    pub decisions: Vec<models::purge::PurgeDecision>,
 }
 
 impl Report {
     pub fn new(
         queued: Vec<models::purge::PurgeData>,
         removed: Vec<models::purge::PurgeLog>,
+        decisions: Vec<models::purge::PurgeDecision>,
     ) -> Self {
-        return Self { queued, removed };
+        return Self {
+            queued,
+            removed,
+            decisions,
+        };
     }
 }
 
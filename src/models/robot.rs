@@ -1,9 +1,17 @@
+use crate::*;
+
 #[derive(PartialEq, PartialOrd, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Robot {
     #[serde(flatten)]
     pub data: RobotData,
     #[serde(flatten)]
     pub config: RobotConfig,
+    #[serde(
+        rename = "nextRun",
+        skip_deserializing,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub next_run: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(PartialEq, PartialOrd, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
@@ -35,6 +43,71 @@ pub struct RobotData {
     pub active: bool,
     pub created: Option<chrono::DateTime<chrono::Utc>>,
     pub modified: Option<chrono::DateTime<chrono::Utc>>,
+    #[serialize_always]
+    pub breaker: Option<RobotBreaker>,
+    #[serde(rename = "credentialStatus")]
+    pub credential_status: Option<RobotCredentialStatus>,
+    #[serde(rename = "lastRun")]
+    pub last_run: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RobotCredentialState {
+    #[default]
+    Valid,
+    Expired,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct RobotCredentialStatus {
+    pub state: RobotCredentialState,
+    pub since: chrono::DateTime<chrono::Utc>,
+    pub notified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct RobotBreaker {
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub removals: i64,
+    pub users: i64,
+    pub acknowledged: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[serde_with::skip_serializing_none]
@@ -71,27 +144,225 @@ pub struct RobotIdentifier {
 pub struct RobotCredential {
     pub platform_email: String,
     pub platform_api_key: String,
+    #[serde(default)]
     pub cloud_session_token: String,
+    #[serde(default)]
+    pub admin_api_key: String,
+    #[serde(default)]
+    pub user_source: RobotSource,
+    #[serde(default)]
+    pub platform_type: RobotPlatform,
+    #[serde(default)]
+    pub platform_url: String,
+    #[serde(default)]
+    pub personal_access_token: String,
+    #[serde(default)]
+    pub workspace: String,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RobotSource {
+    #[default]
+    AdminHub,
+    Organization,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RobotPlatform {
+    #[default]
+    JiraCloud,
+    JiraDataCenter,
+    ConfluenceCloud,
+    BitbucketCloud,
 }
 
 #[derive(PartialEq, PartialOrd, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RobotScheduler {
     pub schedule: i64,
+    #[serde(default)]
+    pub cron: Option<String>,
+    #[serde(default)]
+    pub timezone: Option<String>,
     pub last_active: i64,
     pub double_name_threshold: f32,
     pub double_email_threshold: f32,
     pub check_double_name: bool,
     pub check_double_email: bool,
     pub check_active_status: bool,
+    #[serde(default)]
+    pub check_project_role: bool,
+    #[serde(default)]
+    pub issue_activity: i64,
+    #[serde(default)]
+    pub stale_invitation: i64,
+    #[serde(default)]
+    pub resend_invitation: bool,
+    #[serde(default)]
+    pub rules: Vec<models::policy::PolicyRule>,
+    #[serde(default)]
+    pub max_removals: i64,
+    #[serde(default)]
+    pub max_removal_percentage: f32,
+    #[serde(default)]
+    pub purge_admins: bool,
+    #[serde(default)]
+    pub actions: Vec<RobotAction>,
+    #[serde(default)]
+    pub include_groups: Vec<String>,
+    #[serde(default)]
+    pub exclude_groups: Vec<String>,
+    #[serde(default)]
+    pub escalation: Option<RobotEscalation>,
+}
+
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RobotManagerSource {
+    File {
+        path: String,
+    },
+    Attribute {
+        field: models::policy::PolicyTextField,
+    },
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct RobotEscalation {
+    pub reminders: i64,
+    pub managers: Option<RobotManagerSource>,
+    #[serde(default)]
+    pub contacts: Vec<String>,
+}
+
+#[derive(
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Hash,
+    Default,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct RobotAction {
+    pub reason: models::purge::PurgeReason,
+    pub action: models::purge::PurgeAction,
+}
+
+impl RobotBreaker {
+    pub fn new(removals: usize, users: usize, time: chrono::DateTime<chrono::Utc>) -> Self {
+        return Self {
+            time,
+            removals: removals as i64,
+            users: users as i64,
+            acknowledged: None,
+        };
+    }
+}
+
+impl RobotCredentialStatus {
+    pub fn new(state: RobotCredentialState, since: chrono::DateTime<chrono::Utc>) -> Self {
+        return Self {
+            state,
+            since,
+            notified: None,
+        };
+    }
 }
 
 impl Robot {
     pub fn new(data: RobotData, config: RobotConfig) -> Self {
-        return Self { data, config };
+        return Self {
+            data,
+            config,
+            next_run: None,
+        };
+    }
+
+    pub fn get_schedule(&self) -> Result<Option<cron::Schedule>, String> {
+        return match &self.config.scheduler.cron {
+            Some(cron) => cron
+                .parse::<cron::Schedule>()
+                .map(Some)
+                .map_err(|error| format!("'cron' is invalid ({})", error)),
+            None => Ok(None),
+        };
+    }
+
+    pub fn get_timezone(&self) -> Result<chrono_tz::Tz, String> {
+        return match &self.config.scheduler.timezone {
+            Some(timezone) => timezone
+                .parse::<chrono_tz::Tz>()
+                .map_err(|error| format!("'timezone' is invalid ({})", error)),
+            None => Ok(chrono_tz::UTC),
+        };
+    }
+
+    pub fn validate_schedule(&self) -> Result<(), String> {
+        self.get_schedule()?;
+        self.get_timezone()?;
+        return Ok(());
+    }
+
+    pub fn get_next_run(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        // Robots without a cron of their own follow the global schedule
+        let schedule = self.get_schedule().ok()??;
+        let timezone = self.get_timezone().ok()?;
+        let next_run = match self.data.last_run.or(self.data.created) {
+            Some(time) => schedule.after(&time.with_timezone(&timezone)).next(),
+            None => schedule.upcoming(timezone).next(),
+        };
+        return next_run.map(|time| time.with_timezone(&chrono::Utc));
     }
 
     pub fn is_updated(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        // Robots with a cron of their own analyze their users on every run
+        if self.config.scheduler.cron.is_some() {
+            return false;
+        }
         if let Some(modified) = self.data.modified {
             if now <= modified + chrono::Duration::days(self.config.scheduler.schedule) {
                 return true;
@@ -99,4 +370,266 @@ impl Robot {
         }
         return false;
     }
+
+    pub fn is_credential_expired(&self) -> bool {
+        return self
+            .data
+            .credential_status
+            .as_ref()
+            .is_some_and(|status| status.state == RobotCredentialState::Expired);
+    }
+
+    pub fn exceeds_removal_limits(&self, removals: usize, users: usize) -> bool {
+        let scheduler = &self.config.scheduler;
+        if scheduler.max_removals > 0 && removals as i64 > scheduler.max_removals {
+            return true;
+        }
+        if scheduler.max_removal_percentage > 0.0
+            && users > 0
+            && removals as f32 / users as f32 * 100.0 > scheduler.max_removal_percentage
+        {
+            return true;
+        }
+        return false;
+    }
+
+    pub fn email_owner(
+        &self,
+        notification_email: &str,
+        notification_password: &str,
+        subject: &str,
+        message: &str,
+        copies: &[String],
+    ) -> bool {
+        let relay = lettre::SmtpTransport::relay("smtp.gmail.com")
+            .unwrap()
+            .credentials(lettre::transport::smtp::authentication::Credentials::new(
+                notification_email.to_string(),
+                notification_password.to_string(),
+            ))
+            .build();
+
+        let body = format!(
+            r#"
+            <!DOCTYPE html>
+            <html>
+              <head>
+                <title>{0}</title>
+              </head>
+              <body style="font-family: Arial, sans-serif; font-size: 14px; line-height: 1.5;">
+                <h1 style="font-size: 24px;">{0}</h1>
+                <p>Robot: {1} ({2})</p>
+                <p>{3}</p>
+              </body>
+            </html>
+            "#,
+            subject,
+            self.data.name,
+            self.data
+                .id
+                .unique
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            message
+        );
+
+        let mut builder = lettre::Message::builder()
+            .from(
+                format!("Telkom Developer Network <{}>", notification_email)
+                    .parse()
+                    .unwrap(),
+            )
+            .to(match self.config.credential.platform_email.parse() {
+                Ok(mailbox) => mailbox,
+                Err(_) => return false,
+            });
+        for copy in copies {
+            match copy.parse() {
+                Ok(mailbox) => builder = builder.cc(mailbox),
+                Err(_error) => log::warn!("Robot {:?} can't copy {:?}", self.data.name, copy),
+            }
+        }
+        let message = builder
+            .subject(subject)
+            .header(lettre::message::header::ContentType::TEXT_HTML)
+            .body(body)
+            .unwrap();
+
+        return lettre::Transport::send(&relay, &message).is_ok();
+    }
+
+    pub fn get_groups(&self) -> Vec<&String> {
+        let mut groups = self
+            .config
+            .scheduler
+            .include_groups
+            .iter()
+            .chain(self.config.scheduler.exclude_groups.iter())
+            .collect::<Vec<_>>();
+        groups.sort();
+        groups.dedup();
+        return groups;
+    }
+
+    pub fn is_user_purgeable(
+        &self,
+        user_id: &String,
+        memberships: &models::jira::GroupMemberships,
+    ) -> bool {
+        let is_member = |groups: &Vec<String>| {
+            return groups.iter().any(|group| {
+                return memberships
+                    .get(group)
+                    .is_some_and(|members| members.contains(user_id));
+            });
+        };
+
+        // Members of an excluded group are never purged
+        if is_member(&self.config.scheduler.exclude_groups) {
+            return false;
+        }
+
+        // When groups are included, only their members can be purged
+        if !self.config.scheduler.include_groups.is_empty()
+            && !is_member(&self.config.scheduler.include_groups)
+        {
+            return false;
+        }
+
+        return true;
+    }
+
+    pub fn get_purge_action(
+        &self,
+        reasons: &[models::purge::PurgeReason],
+    ) -> models::purge::PurgeAction {
+        // Every reason maps to its configured action (delete if none), the most severe one wins
+        let actions = reasons
+            .iter()
+            .map(|reason| {
+                return self
+                    .config
+                    .scheduler
+                    .actions
+                    .iter()
+                    .find(|action| action.reason == *reason)
+                    .map(|action| action.action.clone())
+                    .unwrap_or_default();
+            })
+            .collect::<Vec<_>>();
+
+        // Group removals add up, so the user leaves the groups of every reason
+        return match actions.iter().max().cloned().unwrap_or_default() {
+            models::purge::PurgeAction::RemoveFromGroups { .. } => {
+                models::purge::PurgeAction::RemoveFromGroups {
+                    groups: actions
+                        .into_iter()
+                        .filter_map(|action| match action {
+                            models::purge::PurgeAction::RemoveFromGroups { groups } => Some(groups),
+                            _ => None,
+                        })
+                        .flatten()
+                        .collect::<std::collections::BTreeSet<_>>()
+                        .into_iter()
+                        .collect(),
+                }
+            }
+            action => action,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn get_purge_action_takes_the_most_severe_action() {
+        let mut robot = models::robot::Robot::default();
+        robot.config.scheduler.actions = vec![
+            models::robot::RobotAction {
+                reason: models::purge::PurgeReason::LastActive,
+                action: models::purge::PurgeAction::RemoveFromGroups {
+                    groups: vec!["developers".to_string(), "staff".to_string()],
+                },
+            },
+            models::robot::RobotAction {
+                reason: models::purge::PurgeReason::NoProjectRole,
+                action: models::purge::PurgeAction::RemoveFromGroups {
+                    groups: vec!["staff".to_string(), "contractors".to_string()],
+                },
+            },
+            models::robot::RobotAction {
+                reason: models::purge::PurgeReason::StaleInvitation,
+                action: models::purge::PurgeAction::RevokeProduct {
+                    product: "jira-software".to_string(),
+                },
+            },
+        ];
+
+        // Group removals of several reasons are merged
+        assert_eq!(
+            robot.get_purge_action(&[
+                models::purge::PurgeReason::LastActive,
+                models::purge::PurgeReason::NoProjectRole,
+                models::purge::PurgeReason::StaleInvitation,
+            ]),
+            models::purge::PurgeAction::RemoveFromGroups {
+                groups: vec![
+                    "contractors".to_string(),
+                    "developers".to_string(),
+                    "staff".to_string()
+                ],
+            }
+        );
+
+        // Reasons without an action delete the user
+        assert_eq!(
+            robot.get_purge_action(&[
+                models::purge::PurgeReason::LastActive,
+                models::purge::PurgeReason::ActiveStatus,
+            ]),
+            models::purge::PurgeAction::Delete
+        );
+        assert_eq!(
+            robot.get_purge_action(&[models::purge::PurgeReason::StaleInvitation]),
+            models::purge::PurgeAction::RevokeProduct {
+                product: "jira-software".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn get_next_run_follows_the_robots_cron() {
+        let mut robot = models::robot::Robot::default();
+        let created =
+            chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 1, 12, 0, 0).unwrap();
+        robot.data.created = Some(created);
+
+        // Robots without a cron follow the global schedule
+        assert_eq!(robot.get_next_run(), None);
+
+        // Every day at 6:00, counted from the creation until the robot first runs
+        robot.config.scheduler.cron = Some("0 0 6 * * *".to_string());
+        assert_eq!(
+            robot.get_next_run(),
+            Some(chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 2, 6, 0, 0).unwrap())
+        );
+        robot.data.last_run = Some(created + chrono::Duration::days(3));
+        assert_eq!(
+            robot.get_next_run(),
+            Some(chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 5, 6, 0, 0).unwrap())
+        );
+
+        // The cron is read in the robot's timezone, Berlin is an hour ahead in winter
+        robot.config.scheduler.timezone = Some("Europe/Berlin".to_string());
+        assert_eq!(
+            robot.get_next_run(),
+            Some(chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 5, 5, 0, 0).unwrap())
+        );
+
+        robot.config.scheduler.cron = Some("every day".to_string());
+        assert!(robot.validate_schedule().is_err());
+        assert_eq!(robot.get_next_run(), None);
+    }
 }
@@ -0,0 +1,302 @@
+use crate::*;
+
+pub type Duplicates =
+    std::collections::BTreeMap<String, std::collections::BTreeSet<models::purge::PurgeReason>>;
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotUser {
+    pub robot: mongodb::bson::oid::ObjectId,
+    pub version: Option<mongodb::bson::oid::ObjectId>,
+    pub user: models::jira::User,
+    #[serde(default)]
+    pub duplicates: Duplicates,
+    pub scored: Option<String>,
+    pub updated: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(PartialEq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Snapshot {
+    #[serde(rename = "_id")]
+    pub robot: mongodb::bson::oid::ObjectId,
+    // Only the users written under this version belong to the snapshot
+    #[serde(default)]
+    pub version: Option<mongodb::bson::oid::ObjectId>,
+    pub fetched: chrono::DateTime<chrono::Utc>,
+    pub total: usize,
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    #[serde(default)]
+    pub scoring: String,
+}
+
+#[derive(PartialEq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotUsers {
+    #[serde(flatten)]
+    pub snapshot: Snapshot,
+    pub users: Vec<DirectoryUser>,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl SnapshotUser {
+    pub fn new(
+        robot: &models::robot::Robot,
+        user: &models::jira::User,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        return Self {
+            robot: robot.data.id.unique.unwrap(),
+            version: None,
+            user: user.clone(),
+            duplicates: Duplicates::new(),
+            scored: None,
+            updated: now,
+        };
+    }
+}
+
+impl Snapshot {
+    pub fn new(
+        robot: &models::robot::Robot,
+        total: usize,
+        diff: SnapshotDiff,
+        scoring: String,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        return Self {
+            robot: robot.data.id.unique.unwrap(),
+            version: Some(mongodb::bson::oid::ObjectId::new()),
+            fetched: now,
+            total,
+            added: diff.added,
+            changed: diff.changed,
+            removed: diff.removed,
+            scoring,
+        };
+    }
+}
+
+impl SnapshotDiff {
+    pub fn new(
+        previous: &std::collections::HashMap<String, SnapshotUser>,
+        users: &Vec<models::jira::User>,
+    ) -> Self {
+        let mut diff = Self::default();
+        for user in users {
+            match previous.get(&user.id) {
+                Some(snapshot) if snapshot.user == *user => {}
+                Some(_snapshot) => diff.changed.push(user.id.clone()),
+                None => diff.added.push(user.id.clone()),
+            }
+        }
+        let current = users
+            .iter()
+            .map(|user| &user.id)
+            .collect::<std::collections::HashSet<_>>();
+        diff.removed = previous
+            .keys()
+            .filter(|id| !current.contains(id))
+            .cloned()
+            .collect();
+        diff.removed.sort();
+        return diff;
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DirectorySort {
+    #[default]
+    Created,
+    DisplayName,
+    Email,
+    Presence,
+    DaysInactive,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DirectoryOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DirectoryFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(PartialEq, Eq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryQuery {
+    pub search: Option<String>,
+    pub inactive_days: Option<i64>,
+    pub admin: Option<bool>,
+    pub unverified: Option<bool>,
+    #[serde(default)]
+    pub sort: DirectorySort,
+    #[serde(default)]
+    pub order: DirectoryOrder,
+    #[serde(default)]
+    pub format: DirectoryFormat,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryUser {
+    #[serde(flatten)]
+    pub user: models::jira::User,
+    pub effective_presence: Option<chrono::DateTime<chrono::Utc>>,
+    pub days_inactive: Option<i64>,
+    pub queue_status: Option<models::purge::PurgeState>,
+    pub reasons: Vec<models::purge::PurgeReason>,
+}
+
+impl DirectoryUser {
+    pub fn new(
+        user: models::jira::User,
+        purge: Option<&models::purge::PurgeData>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        let effective_presence = user.get_available_presence();
+        return Self {
+            user,
+            effective_presence,
+            days_inactive: effective_presence
+                .map(|presence| std::cmp::max((now - presence).num_days(), 0)),
+            queue_status: purge.map(|purge| purge.state),
+            reasons: purge.map_or(Vec::new(), |purge| purge.reasons.clone()),
+        };
+    }
+}
+
+impl DirectoryQuery {
+    pub fn matches(&self, user: &DirectoryUser) -> bool {
+        if let Some(search) = &self.search {
+            let search = search.to_lowercase();
+            if !user.user.display_name.to_lowercase().contains(&search)
+                && !user.user.email.to_lowercase().contains(&search)
+                && !user.user.id.to_lowercase().contains(&search)
+            {
+                return false;
+            }
+        }
+        if let Some(days) = self.inactive_days {
+            if user.days_inactive.is_none_or(|inactive| inactive <= days) {
+                return false;
+            }
+        }
+        if let Some(admin) = self.admin {
+            if user.user.is_privileged() != admin {
+                return false;
+            }
+        }
+        if let Some(unverified) = self.unverified {
+            if user.user.has_verified_email == unverified {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    pub fn sort(&self, users: &mut [DirectoryUser]) {
+        match self.sort {
+            DirectorySort::Created => users.sort_by_key(|user| user.user.created),
+            DirectorySort::DisplayName => {
+                users.sort_by_key(|user| user.user.display_name.to_lowercase())
+            }
+            DirectorySort::Email => users.sort_by_key(|user| user.user.email.to_lowercase()),
+            DirectorySort::Presence => users.sort_by_key(|user| user.effective_presence),
+            DirectorySort::DaysInactive => users.sort_by_key(|user| user.days_inactive),
+        }
+        if self.order == DirectoryOrder::Desc {
+            users.reverse();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn directory_query_matches_every_filter() {
+        let user = models::snapshot::DirectoryUser {
+            user: models::jira::User {
+                id: "alice".to_string(),
+                display_name: "Alice Smith".to_string(),
+                email: "alice@example.com".to_string(),
+                site_admin: true,
+                has_verified_email: false,
+                ..Default::default()
+            },
+            days_inactive: Some(100),
+            ..Default::default()
+        };
+        let matches = |query: models::snapshot::DirectoryQuery| query.matches(&user);
+
+        assert!(matches(models::snapshot::DirectoryQuery::default()));
+
+        // Searches the name, email and id regardless of case
+        assert!(matches(models::snapshot::DirectoryQuery {
+            search: Some("SMITH".to_string()),
+            ..Default::default()
+        }));
+        assert!(matches(models::snapshot::DirectoryQuery {
+            search: Some("@example".to_string()),
+            ..Default::default()
+        }));
+        assert!(!matches(models::snapshot::DirectoryQuery {
+            search: Some("bob".to_string()),
+            ..Default::default()
+        }));
+
+        assert!(matches(models::snapshot::DirectoryQuery {
+            inactive_days: Some(99),
+            ..Default::default()
+        }));
+        assert!(!matches(models::snapshot::DirectoryQuery {
+            inactive_days: Some(100),
+            ..Default::default()
+        }));
+        assert!(matches(models::snapshot::DirectoryQuery {
+            admin: Some(true),
+            ..Default::default()
+        }));
+        assert!(!matches(models::snapshot::DirectoryQuery {
+            admin: Some(false),
+            ..Default::default()
+        }));
+        assert!(matches(models::snapshot::DirectoryQuery {
+            unverified: Some(true),
+            ..Default::default()
+        }));
+        assert!(!matches(models::snapshot::DirectoryQuery {
+            unverified: Some(false),
+            ..Default::default()
+        }));
+
+        // Users without a known presence are never listed as inactive
+        let user = models::snapshot::DirectoryUser {
+            days_inactive: None,
+            ..user.clone()
+        };
+        assert!(!models::snapshot::DirectoryQuery {
+            inactive_days: Some(0),
+            ..Default::default()
+        }
+        .matches(&user));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::*;
+
+pub async fn get(
+    request: actix_web::HttpRequest,
+    robot_id_path: actix_web::web::Path<String>,
+    forecast_query: actix_web::web::Query<models::forecast::ForecastQuery>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let days = forecast_query.into_inner().days.unwrap_or(30);
+    if !(1..=365).contains(&days) {
+        return Err(errors::error::Error::new(
+            actix_web::http::StatusCode::BAD_REQUEST,
+            "'days' must be between 1 and 365".to_string(),
+        )
+        .into());
+    }
+
+    let robot_id = models::robot::RobotIdentifier {
+        unique: Some(
+            mongodb::bson::oid::ObjectId::parse_str(robot_id_path.into_inner()).map_err(
+                |error| {
+                    errors::error::Error::new(
+                        actix_web::http::StatusCode::BAD_REQUEST,
+                        format!("'id' is invalid ({})", error),
+                    )
+                },
+            )?,
+        ),
+    };
+
+    let mongodb = request
+        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "MongoDB client not found".to_string(),
+        ))?;
+
+    let rusoto = request
+        .app_data::<actix_web::web::Data<clients::rusoto::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Rusoto client not found".to_string(),
+        ))?;
+
+    let robot_data = mongodb
+        .get_robot(&robot_id)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!(
+                "Robot with unique id ({:?}) doesn't exist",
+                robot_id.unique.unwrap().to_string()
+            ),
+        ))?;
+
+    let robot_config = rusoto
+        .get_robot(&robot_id.unique.unwrap())
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?;
+
+    let robot = models::robot::Robot::new(robot_data, robot_config);
+    if robot.config.scheduler.last_active <= 0 {
+        return Err(errors::error::Error::new(
+            actix_web::http::StatusCode::BAD_REQUEST,
+            format!(
+                "Robot with unique id ({:?}) doesn't purge inactive users",
+                robot_id.unique.unwrap().to_string()
+            ),
+        )
+        .into());
+    }
+
+    let snapshot = mongodb
+        .get_snapshot(&robot_id)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!(
+                "Robot with unique id ({:?}) has no snapshot yet",
+                robot_id.unique.unwrap().to_string()
+            ),
+        ))?;
+
+    let users = mongodb
+        .get_snapshot_users(&snapshot)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?;
+
+    let queued = mongodb
+        .get_purge_users()
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .into_iter()
+        .filter(|purge_user| purge_user.robot.id == robot_id.unique.unwrap())
+        .map(|purge_user| purge_user.user.id)
+        .collect::<std::collections::HashSet<_>>();
+
+    // Users already in the queue are out of the forecast
+    let users = users
+        .into_iter()
+        .filter(|user| !queued.contains(&user.user.id))
+        .collect();
+
+    let exemptions = mongodb
+        .get_purge_exemptions(&robot_id)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?;
+
+    let reqwest = request
+        .app_data::<actix_web::web::Data<clients::reqwest::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Reqwest client not found".to_string(),
+        ))?;
+
+    let memberships = clients::source::new(reqwest, &robot)
+        .get_group_memberships(&robot)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(actix_web::http::StatusCode::BAD_GATEWAY, error.to_string())
+        })?;
+
+    return Ok(
+        actix_web::HttpResponse::Ok().json(models::forecast::Forecast::new(
+            &robot,
+            &snapshot,
+            users,
+            &memberships,
+            &exemptions,
+            days,
+            chrono::Utc::now(),
+        )),
+    );
+}
@@ -1,2 +1,5 @@
+pub mod forecast;
+pub mod purge;
 pub mod report;
 pub mod robots;
+pub mod users;
@@ -0,0 +1,268 @@
+use crate::*;
+
+pub async fn approve(
+    request: actix_web::HttpRequest,
+    purge_id_query: actix_web::web::Query<models::purge::PurgeIdentifier>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let purge_id = purge_id_query.into_inner();
+
+    purge_id.unique.ok_or(errors::error::Error::new(
+        actix_web::http::StatusCode::BAD_REQUEST,
+        "'_id' can't be 'None'".to_string(),
+    ))?;
+
+    let mongodb = request
+        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "MongoDB client not found".to_string(),
+        ))?;
+
+    let now = chrono::Utc::now();
+
+    let purge_data = mongodb
+        .approve_purge_user(&purge_id, now)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!(
+                "Protected purge with id {} couldn't be found",
+                purge_id.unique.unwrap()
+            ),
+        ))?;
+
+    mongodb
+        .add_purge_decision(&models::purge::PurgeDecision::new(
+            &purge_data,
+            models::purge::PurgeProtection::Approved,
+            now,
+        ))
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?;
+
+    return Ok(actix_web::HttpResponse::Ok().json(purge_data));
+}
+
+pub async fn retry(
+    request: actix_web::HttpRequest,
+    purge_id_query: actix_web::web::Query<models::purge::PurgeIdentifier>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let purge_id = purge_id_query.into_inner();
+
+    purge_id.unique.ok_or(errors::error::Error::new(
+        actix_web::http::StatusCode::BAD_REQUEST,
+        "'_id' can't be 'None'".to_string(),
+    ))?;
+
+    let mongodb = request
+        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "MongoDB client not found".to_string(),
+        ))?;
+
+    let purge_data = mongodb
+        .retry_purge_user(&purge_id)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!(
+                "Failed purge with id {} couldn't be found",
+                purge_id.unique.unwrap()
+            ),
+        ))?;
+
+    return Ok(actix_web::HttpResponse::Ok().json(purge_data));
+}
+
+pub async fn confirm_keep(
+    request: actix_web::HttpRequest,
+    purge_token_query: actix_web::web::Query<models::purge::PurgeToken>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let purge_token = purge_token_query.into_inner();
+
+    let notification_config = request
+        .app_data::<actix_web::web::Data<configs::notification::Config>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Notification config not found".to_string(),
+        ))?;
+
+    let secret = notification_config
+        .keep_secret
+        .as_ref()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            "Keeping accounts isn't enabled".to_string(),
+        ))?;
+
+    // Only verified tokens are echoed back, and those are made of hex, digits, dots and base64url
+    utils::token::verify(secret, &purge_token.token, chrono::Utc::now()).map_err(|error| {
+        errors::error::Error::new(actix_web::http::StatusCode::FORBIDDEN, error)
+    })?;
+
+    // Link scanners follow GET requests, so the account is only kept once the form is submitted
+    return Ok(actix_web::HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            "<!DOCTYPE html>
+<html>
+<head><title>Keep My Account</title></head>
+<body>
+<p>Your account is scheduled for removal because it has been inactive.</p>
+<form method=\"post\" action=\"keep\">
+<input type=\"hidden\" name=\"token\" value=\"{}\">
+<button type=\"submit\">Keep My Account</button>
+</form>
+</body>
+</html>",
+            purge_token.token
+        )));
+}
+
+pub async fn keep(
+    request: actix_web::HttpRequest,
+    purge_token_form: actix_web::web::Form<models::purge::PurgeToken>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let purge_token = purge_token_form.into_inner();
+
+    let mongodb = request
+        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "MongoDB client not found".to_string(),
+        ))?;
+
+    let notification_config = request
+        .app_data::<actix_web::web::Data<configs::notification::Config>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Notification config not found".to_string(),
+        ))?;
+
+    let secret = notification_config
+        .keep_secret
+        .as_ref()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            "Keeping accounts isn't enabled".to_string(),
+        ))?;
+
+    let now = chrono::Utc::now();
+
+    let purge_id = models::purge::PurgeIdentifier {
+        unique: Some(
+            utils::token::verify(secret, &purge_token.token, now).map_err(|error| {
+                errors::error::Error::new(actix_web::http::StatusCode::FORBIDDEN, error)
+            })?,
+        ),
+    };
+
+    let purge_data = match mongodb.get_purge_user(&purge_id).await.map_err(|error| {
+        errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            error.to_string(),
+        )
+    })? {
+        Some(purge_data) => purge_data,
+        None => {
+            // Submitting the form twice shouldn't look like a failure
+            let exemption = mongodb
+                .get_purge_exemption(&purge_id)
+                .await
+                .map_err(|error| {
+                    errors::error::Error::new(
+                        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        error.to_string(),
+                    )
+                })?
+                .ok_or(errors::error::Error::new(
+                    actix_web::http::StatusCode::NOT_FOUND,
+                    format!(
+                        "Purge with id {} is no longer waiting for removal",
+                        purge_id.unique.unwrap()
+                    ),
+                ))?;
+            return Ok(actix_web::HttpResponse::Ok().json(exemption));
+        }
+    };
+
+    let removal_error = errors::error::Error::new(
+        actix_web::http::StatusCode::CONFLICT,
+        format!(
+            "Removal of purge with id {} has already started",
+            purge_id.unique.unwrap()
+        ),
+    );
+
+    if !matches!(
+        purge_data.state,
+        models::purge::PurgeState::Queued | models::purge::PurgeState::Warned
+    ) || purge_data.attempts > 0
+    {
+        return Err(removal_error.into());
+    }
+
+    // The exemption is written first, so the confirmation isn't lost if taking the user off the queue fails
+    let exemption = models::purge::PurgeExemption::new(
+        &purge_data,
+        now,
+        now + notification_config.keep_exemption,
+    );
+
+    mongodb
+        .add_purge_exemption(&exemption)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?;
+
+    let kept = mongodb.keep_purge_user(&purge_id).await.map_err(|error| {
+        errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            error.to_string(),
+        )
+    })?;
+
+    if kept.is_none() {
+        // The removal started in between, so the exemption would only hide it
+        mongodb
+            .delete_purge_exemption(&purge_id)
+            .await
+            .map_err(|error| {
+                errors::error::Error::new(
+                    actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    error.to_string(),
+                )
+            })?;
+        return Err(removal_error.into());
+    }
+
+    log::info!(
+        "User {:?} asked to keep their account until {}",
+        purge_data.user.display_name,
+        exemption.until
+    );
+
+    return Ok(actix_web::HttpResponse::Ok().json(exemption));
+}
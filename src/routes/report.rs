@@ -109,7 +109,13 @@ pub async fn get_report(
 
     let purge_log = mongodb.get_purge_log().await?;
 
-    return Ok(models::report::Report::new(purge_data, purge_log));
+    let purge_decisions = mongodb.get_purge_decisions(robot_id).await?;
+
+    return Ok(models::report::Report::new(
+        purge_data,
+        purge_log,
+        purge_decisions,
+    ));
 }
 
 pub async fn get(
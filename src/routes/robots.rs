@@ -1,5 +1,24 @@
 use crate::*;
 
+fn with_next_run(
+    mut robot: models::robot::Robot,
+    scheduler_config: &configs::scheduler::Config,
+) -> models::robot::Robot {
+    // Inactive robots don't run, the others follow their own cron or the global schedule
+    if robot.data.active {
+        robot.next_run = robot
+            .get_next_run()
+            .or_else(|| scheduler_config.schedule.upcoming(chrono::Utc).next());
+    }
+    return robot;
+}
+
+fn wake_scheduler(request: &actix_web::HttpRequest) {
+    if let Some(wake) = request.app_data::<actix_web::web::Data<tokio::sync::Notify>>() {
+        wake.notify_one();
+    }
+}
+
 pub async fn get(
     request: actix_web::HttpRequest,
     robot_id_query: actix_web::web::Query<models::robot::RobotIdentifier>,
@@ -20,6 +39,13 @@ pub async fn get(
             "Rusoto client not found".to_string(),
         ))?;
 
+    let scheduler_config = request
+        .app_data::<actix_web::web::Data<configs::scheduler::Config>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Scheduler config not found".to_string(),
+        ))?;
+
     if let Some(id) = robot_id.unique {
         let robot_data = mongodb
             .get_robot(&robot_id)
@@ -42,8 +68,10 @@ pub async fn get(
             )
         })?;
 
-        return Ok(actix_web::HttpResponse::Found()
-            .json(models::robot::Robot::new(robot_data, robot_config)));
+        return Ok(actix_web::HttpResponse::Found().json(with_next_run(
+            models::robot::Robot::new(robot_data, robot_config),
+            scheduler_config,
+        )));
     }
 
     let robots_data = mongodb.get_robots().await.map_err(|error| {
@@ -66,7 +94,10 @@ pub async fn get(
                 )
             })
         {
-            robots.push(models::robot::Robot::new(robot_data, robot_config));
+            robots.push(with_next_run(
+                models::robot::Robot::new(robot_data, robot_config),
+                scheduler_config,
+            ));
         }
     }
 
@@ -79,6 +110,19 @@ pub async fn post(
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
     let mut robot = robot_json.into_inner();
 
+    utils::policy::validate(&robot).map_err(|error| {
+        errors::error::Error::new(actix_web::http::StatusCode::BAD_REQUEST, error)
+    })?;
+
+    robot.validate_schedule().map_err(|error| {
+        errors::error::Error::new(actix_web::http::StatusCode::BAD_REQUEST, error)
+    })?;
+
+    // The scheduler manages these, a new robot starts without them
+    robot.data.breaker = None;
+    robot.data.credential_status = None;
+    robot.data.last_run = None;
+
     let reqwest = request
         .app_data::<actix_web::web::Data<clients::reqwest::Client>>()
         .ok_or(errors::error::Error::new(
@@ -88,7 +132,7 @@ pub async fn post(
 
     let valid_credential =
         reqwest
-            .check_jira_credentials(&robot)
+            .check_credentials(&robot)
             .await
             .or(Err(errors::error::Error::new(
                 actix_web::http::StatusCode::UNAUTHORIZED,
@@ -102,16 +146,19 @@ pub async fn post(
         .into());
     }
 
-    let users = reqwest
-        .get_jira_users(&robot.config.credential.cloud_session_token)
-        .await;
-    if users.is_empty() {
-        return Err(errors::error::Error::new(
-            actix_web::http::StatusCode::UNAUTHORIZED,
-            "Invalid cloud session token".to_string(),
-        )
-        .into());
-    }
+    clients::source::new(reqwest, &robot)
+        .get_users(&robot)
+        .await
+        .map_err(|error| match error {
+            errors::atlassian::Error::Unauthorized => errors::error::Error::new(
+                actix_web::http::StatusCode::UNAUTHORIZED,
+                "Invalid cloud session token, admin API key or personal access token".to_string(),
+            ),
+            error => errors::error::Error::new(
+                actix_web::http::StatusCode::BAD_GATEWAY,
+                error.to_string(),
+            ),
+        })?;
 
     let mongodb = request
         .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
@@ -136,6 +183,8 @@ pub async fn post(
 
     rusoto.add_robot(&robot).await?;
 
+    wake_scheduler(&request);
+
     return Ok(actix_web::HttpResponse::Created().json(robot));
 }
 
@@ -150,6 +199,44 @@ pub async fn patch(
         "'_id' can't be 'None'".to_string(),
     ))?;
 
+    utils::policy::validate(&robot).map_err(|error| {
+        errors::error::Error::new(actix_web::http::StatusCode::BAD_REQUEST, error)
+    })?;
+
+    robot.validate_schedule().map_err(|error| {
+        errors::error::Error::new(actix_web::http::StatusCode::BAD_REQUEST, error)
+    })?;
+
+    let mongodb = request
+        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "MongoDB client not found".to_string(),
+        ))?;
+
+    let robot_data = mongodb
+        .get_robot(&robot.data.id)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!(
+                "Robot with id {} couldn't be found",
+                robot.data.id.unique.unwrap()
+            ),
+        ))?;
+
+    // The scheduler manages these, and only /robots/resume may lift the breaker
+    robot.data.created = robot_data.created;
+    robot.data.breaker = robot_data.breaker;
+    robot.data.credential_status = robot_data.credential_status;
+    robot.data.last_run = robot_data.last_run;
+
     let reqwest = request
         .app_data::<actix_web::web::Data<clients::reqwest::Client>>()
         .ok_or(errors::error::Error::new(
@@ -159,7 +246,7 @@ pub async fn patch(
 
     let valid_credential =
         reqwest
-            .check_jira_credentials(&robot)
+            .check_credentials(&robot)
             .await
             .or(Err(errors::error::Error::new(
                 actix_web::http::StatusCode::UNAUTHORIZED,
@@ -173,23 +260,19 @@ pub async fn patch(
         .into());
     }
 
-    let users = reqwest
-        .get_jira_users(&robot.config.credential.cloud_session_token)
-        .await;
-    if users.is_empty() {
-        return Err(errors::error::Error::new(
-            actix_web::http::StatusCode::UNAUTHORIZED,
-            "Invalid cloud session token".to_string(),
-        )
-        .into());
-    }
-
-    let mongodb = request
-        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
-        .ok_or(errors::error::Error::new(
-            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-            "MongoDB client not found".to_string(),
-        ))?;
+    clients::source::new(reqwest, &robot)
+        .get_users(&robot)
+        .await
+        .map_err(|error| match error {
+            errors::atlassian::Error::Unauthorized => errors::error::Error::new(
+                actix_web::http::StatusCode::UNAUTHORIZED,
+                "Invalid cloud session token, admin API key or personal access token".to_string(),
+            ),
+            error => errors::error::Error::new(
+                actix_web::http::StatusCode::BAD_GATEWAY,
+                error.to_string(),
+            ),
+        })?;
 
     mongodb.patch_robot(&mut robot).await.map_err(|error| {
         errors::error::Error::new(
@@ -207,6 +290,8 @@ pub async fn patch(
 
     rusoto.patch_robot(&robot).await?;
 
+    wake_scheduler(&request);
+
     return Ok(actix_web::HttpResponse::Ok().json(robot));
 }
 
@@ -257,3 +342,61 @@ pub async fn delete(
 
     return Ok(actix_web::HttpResponse::Ok().finish());
 }
+
+pub async fn resume(
+    request: actix_web::HttpRequest,
+    robot_id_query: actix_web::web::Query<models::robot::RobotIdentifier>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let robot_id = robot_id_query.into_inner();
+
+    robot_id.unique.ok_or(errors::error::Error::new(
+        actix_web::http::StatusCode::BAD_REQUEST,
+        "'_id' can't be 'None'".to_string(),
+    ))?;
+
+    let mongodb = request
+        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "MongoDB client not found".to_string(),
+        ))?;
+
+    let robot_data = mongodb
+        .get_robot(&robot_id)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!(
+                "Robot with id {} couldn't be found",
+                robot_id.unique.unwrap()
+            ),
+        ))?;
+
+    if robot_data.breaker.is_none() {
+        return Err(errors::error::Error::new(
+            actix_web::http::StatusCode::CONFLICT,
+            format!("Robot with id {} isn't paused", robot_id.unique.unwrap()),
+        )
+        .into());
+    }
+
+    mongodb
+        .resume_robot(&robot_id, chrono::Utc::now())
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?;
+
+    wake_scheduler(&request);
+
+    return Ok(actix_web::HttpResponse::Ok().finish());
+}
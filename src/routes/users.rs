@@ -0,0 +1,178 @@
+use crate::*;
+
+pub async fn get(
+    request: actix_web::HttpRequest,
+    robot_id_path: actix_web::web::Path<String>,
+    directory_query: actix_web::web::Query<models::snapshot::DirectoryQuery>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let directory = directory_query.into_inner();
+
+    let robot_id = models::robot::RobotIdentifier {
+        unique: Some(
+            mongodb::bson::oid::ObjectId::parse_str(robot_id_path.into_inner()).map_err(
+                |error| {
+                    errors::error::Error::new(
+                        actix_web::http::StatusCode::BAD_REQUEST,
+                        format!("'id' is invalid ({})", error),
+                    )
+                },
+            )?,
+        ),
+    };
+
+    let mongodb = request
+        .app_data::<actix_web::web::Data<clients::mongodb::Client>>()
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "MongoDB client not found".to_string(),
+        ))?;
+
+    let snapshot = mongodb
+        .get_snapshot(&robot_id)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .ok_or(errors::error::Error::new(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!(
+                "Robot with unique id ({:?}) has no snapshot yet",
+                robot_id.unique.unwrap().to_string()
+            ),
+        ))?;
+
+    let purge_data = mongodb
+        .get_purge_users()
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .into_iter()
+        .filter(|purge_user| purge_user.robot.id == robot_id.unique.unwrap())
+        .map(|purge_user| (purge_user.user.id.clone(), purge_user))
+        .collect::<std::collections::HashMap<_, _>>();
+
+    let now = chrono::Utc::now();
+    let mut users = mongodb
+        .get_snapshot_users(&snapshot)
+        .await
+        .map_err(|error| {
+            errors::error::Error::new(
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+                error.to_string(),
+            )
+        })?
+        .into_iter()
+        .map(|snapshot| {
+            let purge = purge_data.get(&snapshot.user.id);
+            return models::snapshot::DirectoryUser::new(snapshot.user, purge, now);
+        })
+        .filter(|user| directory.matches(user))
+        .collect::<Vec<_>>();
+    directory.sort(&mut users);
+
+    if directory.format == models::snapshot::DirectoryFormat::Csv {
+        return Ok(actix_web::HttpResponse::Ok()
+            .content_type("text/csv")
+            .insert_header((
+                actix_web::http::header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"users-{}.csv\"",
+                    robot_id.unique.unwrap()
+                ),
+            ))
+            .body(to_csv(&users)));
+    }
+
+    return Ok(
+        actix_web::HttpResponse::Ok().json(models::snapshot::SnapshotUsers { snapshot, users })
+    );
+}
+
+fn escape_csv(value: &str) -> String {
+    // Spreadsheets run cells starting with these as formulas, display names are user-controlled
+    let value = if value.starts_with(&['=', '+', '-', '@'][..]) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    return value;
+}
+
+fn get_name<T: serde::Serialize>(value: &T) -> String {
+    return match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    };
+}
+
+fn to_csv(users: &Vec<models::snapshot::DirectoryUser>) -> String {
+    let mut csv = "id,displayName,email,active,admin,verifiedEmail,effectivePresence,daysInactive,queueStatus,reasons\r\n".to_string();
+    for user in users {
+        let fields = [
+            user.user.id.clone(),
+            user.user.display_name.clone(),
+            user.user.email.clone(),
+            user.user.active.to_string(),
+            user.user.is_privileged().to_string(),
+            user.user.has_verified_email.to_string(),
+            user.effective_presence
+                .map_or(String::new(), |presence| presence.to_rfc3339()),
+            user.days_inactive
+                .map_or(String::new(), |days| days.to_string()),
+            user.queue_status
+                .map_or(String::new(), |status| get_name(&status)),
+            user.reasons
+                .iter()
+                .map(|reason| reason.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+        ];
+        csv.push_str(
+            &fields
+                .iter()
+                .map(|field| escape_csv(field))
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        csv.push_str("\r\n");
+    }
+    return csv;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn to_csv_escapes_cells() {
+        let users = vec![models::snapshot::DirectoryUser {
+            user: models::jira::User {
+                id: "alice".to_string(),
+                display_name: "=HYPERLINK(\"x\")".to_string(),
+                email: "alice@example.com".to_string(),
+                ..Default::default()
+            },
+            effective_presence: None,
+            days_inactive: Some(120),
+            queue_status: Some(models::purge::PurgeState::Warned),
+            reasons: vec![
+                models::purge::PurgeReason::LastActive,
+                models::purge::PurgeReason::Custom("Contractor, expired".to_string()),
+            ],
+        }];
+        assert_eq!(
+            super::to_csv(&users).lines().nth(1).unwrap(),
+            "alice,\"'=HYPERLINK(\"\"x\"\")\",alice@example.com,false,false,false,,120,WARNED,\"LAST_ACTIVE;Contractor, expired\""
+        );
+    }
+}
@@ -75,74 +75,6 @@
 //         return users;
 //     }
 
-//     // pub async fn get_jira_project_roles(
-//     //     &self,
-//     //     platform_email: &String,
-//     //     platform_api_key: &String,
-//     // ) -> Vec<models::jira::ProjectRole> {
-//     //     let response = self
-//     //         .reqwest
-//     //         .get(format!(
-//     //             "https://{}.atlassian.net/rest/api/latest/role/",
-//     //             std::env::var("ORGANIZATION_NAME").unwrap()
-//     //         ))
-//     //         .basic_auth(platform_email, Some(platform_api_key))
-//     //         .send()
-//     //         .await;
-//     //     if response.is_err() {
-//     //         return vec![];
-//     //     }
-
-//     //     let text = response.unwrap().text().await;
-//     //     if text.is_err() {
-//     //         return vec![];
-//     //     }
-
-//     //     let data: Result<Vec<models::jira::ProjectRole>, _> =
-//     //         serde_json::from_str(text.unwrap().as_str());
-//     //     if data.is_err() {
-//     //         return vec![];
-//     //     }
-
-//     //     return data.unwrap();
-//     // }
-
-//     // pub async fn get_jira_project_role_actors(
-//     //     &self,
-//     //     platform_email: &String,
-//     //     platform_api_key: &String,
-//     //     project_id: &String,
-//     //     role_id: i64,
-//     // ) -> Vec<models::jira::RoleActor> {
-//     //     let response = self
-//     //         .reqwest
-//     //         .get(format!(
-//     //             "https://{}.atlassian.net/rest/api/latest/project/{}/role/{}",
-//     //             std::env::var("ORGANIZATION_NAME").unwrap(),
-//     //             project_id,
-//     //             role_id
-//     //         ))
-//     //         .basic_auth(platform_email, Some(platform_api_key))
-//     //         .send()
-//     //         .await;
-//     //     if response.is_err() {
-//     //         return vec![];
-//     //     }
-
-//     //     let text = response.unwrap().text().await;
-//     //     if text.is_err() {
-//     //         return vec![];
-//     //     }
-
-//     //     let data: Result<models::jira::ProjectRole, _> =
-//     //         serde_json::from_str(text.unwrap().as_str());
-//     //     if data.is_err() || data.as_ref().unwrap().actors.is_none() {
-//     //         return vec![];
-//     //     }
-
-//     //     return data.unwrap().actors.unwrap();
-//     // }
-
 //     pub async fn patch_robot(
 //         &self,
 //         robot: &models::robot::RobotQuery,
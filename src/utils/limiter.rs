@@ -0,0 +1,112 @@
+struct Bucket {
+    tokens: f64,
+    updated: std::time::Instant,
+}
+
+pub struct Limiter {
+    rate: f64,
+    burst: f64,
+    buckets: std::sync::Mutex<std::collections::HashMap<String, Bucket>>,
+}
+
+impl Limiter {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        return Self {
+            rate,
+            burst,
+            buckets: std::sync::Mutex::new(std::collections::HashMap::new()),
+        };
+    }
+
+    pub async fn acquire(&self, host: &str) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = std::time::Instant::now();
+                let bucket = buckets.entry(host.to_string()).or_insert(Bucket {
+                    tokens: self.burst,
+                    updated: now,
+                });
+
+                // Refill the tokens earned since the last request
+                bucket.tokens = (bucket.tokens
+                    + now.duration_since(bucket.updated).as_secs_f64() * self.rate)
+                    .min(self.burst);
+                bucket.updated = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    None
+                } else {
+                    Some(std::time::Duration::from_secs_f64(
+                        (1.0 - bucket.tokens) / self.rate,
+                    ))
+                }
+            };
+
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+
+    pub fn pause(&self, host: &str, duration: std::time::Duration) {
+        // Go into debt so every request to this host waits out the pause
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = std::time::Instant::now();
+        let bucket = buckets.entry(host.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.tokens.min(-duration.as_secs_f64() * self.rate);
+        bucket.updated = now;
+    }
+}
+
+pub fn backoff(base: std::time::Duration, attempt: u32) -> std::time::Duration {
+    // Exponential backoff with full jitter, capped at one minute
+    let ceiling = base
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(std::time::Duration::from_secs(60));
+    return ceiling.mul_f64(rand::random::<f64>());
+}
+
+pub fn parse_retry_after(
+    value: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<std::time::Duration> {
+    // Retry-After is either a number of seconds or an HTTP date, e.g. "Wed, 21 Oct 2015 07:28:00 GMT"
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    return Some(
+        (date.with_timezone(&chrono::Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn parse_retry_after_reads_seconds_and_dates() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2015, 10, 21, 7, 27, 0).unwrap();
+        assert_eq!(
+            utils::limiter::parse_retry_after("120", now),
+            Some(std::time::Duration::from_secs(120))
+        );
+        assert_eq!(
+            utils::limiter::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(std::time::Duration::from_secs(60))
+        );
+        assert_eq!(
+            utils::limiter::parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(std::time::Duration::ZERO)
+        );
+        assert_eq!(utils::limiter::parse_retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::*;
+
+// Stands in for Atlassian and the S3 bucket, serving users from `tests/fixtures`
+#[derive(Default)]
+pub struct State {
+    pub objects: std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>,
+    pub deleted: std::sync::Mutex<Vec<String>>,
+}
+
+pub struct Server {
+    pub url: String,
+    pub state: actix_web::web::Data<State>,
+    handle: actix_web::dev::ServerHandle,
+}
+
+#[derive(serde::Deserialize)]
+struct UsersQuery {
+    count: usize,
+    #[serde(rename = "start-index")]
+    start_index: usize,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserQuery {
+    account_id: String,
+}
+
+fn read_fixture(name: &str) -> String {
+    return std::fs::read_to_string(format!(
+        "{}/tests/fixtures/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap();
+}
+
+async fn get_users(
+    request: actix_web::HttpRequest,
+    query: actix_web::web::Query<UsersQuery>,
+) -> actix_web::HttpResponse {
+    // Only the "valid" session token is accepted
+    if request
+        .cookie("cloud.session.token")
+        .is_none_or(|cookie| cookie.value() != "valid")
+    {
+        return actix_web::HttpResponse::Unauthorized().finish();
+    }
+
+    let users: models::jira::Users =
+        serde_json::from_str(&read_fixture("admin_users.json")).unwrap();
+    return actix_web::HttpResponse::Ok().json(models::jira::Users {
+        users: users
+            .users
+            .into_iter()
+            .skip(query.start_index.saturating_sub(1))
+            .take(query.count)
+            .collect(),
+        total: users.total,
+    });
+}
+
+async fn get_myself(request: actix_web::HttpRequest) -> actix_web::HttpResponse {
+    if request
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .is_none()
+    {
+        return actix_web::HttpResponse::Unauthorized().finish();
+    }
+    return actix_web::HttpResponse::Ok()
+        .content_type("application/json")
+        .body(read_fixture("myself.json"));
+}
+
+async fn delete_user(
+    state: actix_web::web::Data<State>,
+    query: actix_web::web::Query<UserQuery>,
+) -> actix_web::HttpResponse {
+    state
+        .deleted
+        .lock()
+        .unwrap()
+        .push(query.into_inner().account_id);
+    return actix_web::HttpResponse::NoContent().finish();
+}
+
+async fn get_object(
+    state: actix_web::web::Data<State>,
+    path: actix_web::web::Path<(String, String)>,
+) -> actix_web::HttpResponse {
+    let (bucket, key) = path.into_inner();
+    return match state
+        .objects
+        .lock()
+        .unwrap()
+        .get(&format!("{}/{}", bucket, key))
+    {
+        Some(object) => actix_web::HttpResponse::Ok().body(object.clone()),
+        None => actix_web::HttpResponse::NotFound().finish(),
+    };
+}
+
+async fn put_object(
+    state: actix_web::web::Data<State>,
+    path: actix_web::web::Path<(String, String)>,
+    body: actix_web::web::Bytes,
+) -> actix_web::HttpResponse {
+    let (bucket, key) = path.into_inner();
+    state
+        .objects
+        .lock()
+        .unwrap()
+        .insert(format!("{}/{}", bucket, key), body.to_vec());
+    return actix_web::HttpResponse::Ok().finish();
+}
+
+impl Server {
+    pub async fn start() -> Self {
+        let state = actix_web::web::Data::new(State::default());
+        let data = state.clone();
+        let server = actix_web::HttpServer::new(move || {
+            actix_web::App::new()
+                .app_data(data.clone())
+                .route(
+                    "/gateway/api/adminhub/um/org/{organization}/users",
+                    actix_web::web::get().to(get_users),
+                )
+                .route("/rest/api/3/myself", actix_web::web::get().to(get_myself))
+                .route("/rest/api/3/user", actix_web::web::delete().to(delete_user))
+                .route("/{bucket}/{key:.*}", actix_web::web::get().to(get_object))
+                .route("/{bucket}/{key:.*}", actix_web::web::put().to(put_object))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+        return Self { url, state, handle };
+    }
+
+    pub fn get_deleted(&self) -> Vec<String> {
+        return self.state.deleted.lock().unwrap().clone();
+    }
+
+    pub async fn stop(&self) {
+        self.handle.stop(true).await;
+    }
+}
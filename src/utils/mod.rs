@@ -1,2 +1,8 @@
 pub mod client;
+pub mod limiter;
+#[cfg(test)]
+pub mod mock;
+pub mod policy;
 pub mod scheduler;
+pub mod shutdown;
+pub mod token;
@@ -0,0 +1,353 @@
+use crate::*;
+
+pub fn get_text(
+    user: &models::jira::User,
+    field: models::policy::PolicyTextField,
+) -> Option<&String> {
+    return match field {
+        models::policy::PolicyTextField::DisplayName => Some(&user.display_name),
+        models::policy::PolicyTextField::Email => Some(&user.email),
+        models::policy::PolicyTextField::Title => user.title.as_ref(),
+        models::policy::PolicyTextField::Department => user.department.as_ref(),
+        models::policy::PolicyTextField::CompanyName => user.company_name.as_ref(),
+        models::policy::PolicyTextField::Location => user.location.as_ref(),
+        models::policy::PolicyTextField::Timezone => user.timezone.as_ref(),
+        models::policy::PolicyTextField::ManagedOwner => user.managed_status.owner.as_ref(),
+    };
+}
+
+fn get_flag(user: &models::jira::User, flag: models::policy::PolicyFlag) -> bool {
+    return match flag {
+        models::policy::PolicyFlag::Active => user.active,
+        models::policy::PolicyFlag::HasVerifiedEmail => user.has_verified_email,
+        models::policy::PolicyFlag::Managed => user.managed_status.managed,
+        models::policy::PolicyFlag::OrgAdmin => user.org_admin,
+        models::policy::PolicyFlag::SiteAdmin => user.site_admin,
+        models::policy::PolicyFlag::SysAdmin => user.sys_admin,
+        models::policy::PolicyFlag::TrustedUser => user.trusted_user,
+        models::policy::PolicyFlag::System => user.system,
+    };
+}
+
+pub fn evaluate(
+    condition: &models::policy::PolicyCondition,
+    user: &models::jira::User,
+    now: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    // Text comparisons ignore case, missing fields never equal or contain anything
+    return match condition {
+        models::policy::PolicyCondition::All(conditions) => conditions
+            .iter()
+            .all(|condition| evaluate(condition, user, now)),
+        models::policy::PolicyCondition::Any(conditions) => conditions
+            .iter()
+            .any(|condition| evaluate(condition, user, now)),
+        models::policy::PolicyCondition::Not(condition) => !evaluate(condition, user, now),
+        models::policy::PolicyCondition::Equals { field, value } => {
+            get_text(user, *field).is_some_and(|text| text.to_lowercase() == value.to_lowercase())
+        }
+        models::policy::PolicyCondition::Contains { field, value } => get_text(user, *field)
+            .is_some_and(|text| text.to_lowercase().contains(&value.to_lowercase())),
+        models::policy::PolicyCondition::IsEmpty { field } => {
+            get_text(user, *field).is_none_or(|text| text.trim().is_empty())
+        }
+        models::policy::PolicyCondition::IsTrue { flag } => get_flag(user, *flag),
+        models::policy::PolicyCondition::PresenceOlderThan(days) => user
+            .get_available_presence()
+            .zip(chrono::Duration::try_days(*days).and_then(|days| now.checked_sub_signed(days)))
+            .is_some_and(|(presence, time)| presence <= time),
+    };
+}
+
+pub fn get_reasons(
+    robot: &models::robot::Robot,
+    user: &models::jira::User,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<models::purge::PurgeReason> {
+    return robot
+        .config
+        .scheduler
+        .rules
+        .iter()
+        .filter(|rule| evaluate(&rule.condition, user, now))
+        .map(|rule| models::purge::PurgeReason::Custom(rule.name.clone()))
+        .collect();
+}
+
+pub fn is_reason_valid(
+    robot: &models::robot::Robot,
+    user: &models::jira::User,
+    name: &String,
+    now: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    return robot
+        .config
+        .scheduler
+        .rules
+        .iter()
+        .any(|rule| rule.name == *name && evaluate(&rule.condition, user, now));
+}
+
+fn validate_condition(
+    condition: &models::policy::PolicyCondition,
+    depth: usize,
+) -> Result<(), String> {
+    if depth > 16 {
+        return Err("conditions can't be nested deeper than 16 levels".to_string());
+    }
+    match condition {
+        models::policy::PolicyCondition::All(conditions)
+        | models::policy::PolicyCondition::Any(conditions) => {
+            if conditions.is_empty() {
+                return Err("'all' and 'any' need at least one condition".to_string());
+            }
+            for condition in conditions {
+                validate_condition(condition, depth + 1)?;
+            }
+        }
+        models::policy::PolicyCondition::Not(condition) => {
+            validate_condition(condition, depth + 1)?;
+        }
+        models::policy::PolicyCondition::Equals { value, .. }
+        | models::policy::PolicyCondition::Contains { value, .. } => {
+            if value.is_empty() {
+                return Err("'equals' and 'contains' need a non-empty value".to_string());
+            }
+        }
+        models::policy::PolicyCondition::PresenceOlderThan(days) => {
+            if !(0..=36500).contains(days) {
+                return Err("'presenceOlderThan' must be between 0 and 36500 days".to_string());
+            }
+        }
+        models::policy::PolicyCondition::IsEmpty { .. }
+        | models::policy::PolicyCondition::IsTrue { .. } => {}
+    }
+    return Ok(());
+}
+
+pub fn validate(robot: &models::robot::Robot) -> Result<(), String> {
+    let rules = &robot.config.scheduler.rules;
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.name.trim().is_empty() {
+            return Err(format!("Rule #{} needs a name", i + 1));
+        }
+        if rules[..i].iter().any(|other| other.name == rule.name) {
+            return Err(format!("Rule {:?} is defined more than once", rule.name));
+        }
+        validate_condition(&rule.condition, 0)
+            .map_err(|error| format!("Rule {:?} is invalid ({})", rule.name, error))?;
+    }
+
+    // Actions can only refer to custom reasons that a rule produces
+    for action in &robot.config.scheduler.actions {
+        if let models::purge::PurgeReason::Custom(name) = &action.reason {
+            if !rules.iter().any(|rule| rule.name == *name) {
+                return Err(format!("Action refers to unknown rule {:?}", name));
+            }
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn get_rule(
+        name: &str,
+        condition: models::policy::PolicyCondition,
+    ) -> models::policy::PolicyRule {
+        return models::policy::PolicyRule {
+            name: name.to_string(),
+            condition,
+        };
+    }
+
+    #[test]
+    fn evaluate_applies_every_operator() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 1, 0, 0, 0).unwrap();
+        let user = models::jira::User {
+            email: "Alice@Contractor.example.com".to_string(),
+            department: Some("Sales".to_string()),
+            title: Some("  ".to_string()),
+            active: true,
+            presence: Some(now - chrono::Duration::days(30)),
+            ..Default::default()
+        };
+        let department = |value: &str| models::policy::PolicyCondition::Equals {
+            field: models::policy::PolicyTextField::Department,
+            value: value.to_string(),
+        };
+        let evaluate = |condition: &models::policy::PolicyCondition| {
+            return utils::policy::evaluate(condition, &user, now);
+        };
+
+        // Text comparisons ignore case
+        assert!(evaluate(&department("sales")));
+        assert!(!evaluate(&department("support")));
+        assert!(evaluate(&models::policy::PolicyCondition::Contains {
+            field: models::policy::PolicyTextField::Email,
+            value: "@contractor.".to_string(),
+        }));
+
+        // Missing fields are empty and never equal anything
+        assert!(!evaluate(&models::policy::PolicyCondition::Equals {
+            field: models::policy::PolicyTextField::Location,
+            value: "Berlin".to_string(),
+        }));
+        assert!(evaluate(&models::policy::PolicyCondition::IsEmpty {
+            field: models::policy::PolicyTextField::Location,
+        }));
+        assert!(evaluate(&models::policy::PolicyCondition::IsEmpty {
+            field: models::policy::PolicyTextField::Title,
+        }));
+        assert!(!evaluate(&models::policy::PolicyCondition::IsEmpty {
+            field: models::policy::PolicyTextField::Email,
+        }));
+
+        assert!(evaluate(&models::policy::PolicyCondition::IsTrue {
+            flag: models::policy::PolicyFlag::Active,
+        }));
+        assert!(!evaluate(&models::policy::PolicyCondition::IsTrue {
+            flag: models::policy::PolicyFlag::SiteAdmin,
+        }));
+        assert!(evaluate(
+            &models::policy::PolicyCondition::PresenceOlderThan(30)
+        ));
+        assert!(!evaluate(
+            &models::policy::PolicyCondition::PresenceOlderThan(31)
+        ));
+        assert!(!evaluate(
+            &models::policy::PolicyCondition::PresenceOlderThan(i64::MAX)
+        ));
+        assert!(!evaluate(
+            &models::policy::PolicyCondition::PresenceOlderThan(i64::MAX / 86400)
+        ));
+
+        assert!(evaluate(&models::policy::PolicyCondition::All(vec![
+            department("sales"),
+            models::policy::PolicyCondition::Not(Box::new(department("support"))),
+        ])));
+        assert!(!evaluate(&models::policy::PolicyCondition::All(vec![
+            department("sales"),
+            department("support"),
+        ])));
+        assert!(evaluate(&models::policy::PolicyCondition::Any(vec![
+            department("support"),
+            department("sales"),
+        ])));
+        assert!(!evaluate(&models::policy::PolicyCondition::Any(vec![
+            department("support")
+        ])));
+    }
+
+    #[test]
+    fn validate_rejects_invalid_rules() {
+        let condition = models::policy::PolicyCondition::IsTrue {
+            flag: models::policy::PolicyFlag::Managed,
+        };
+        let validate = |rules: Vec<models::policy::PolicyRule>,
+                        actions: Vec<models::robot::RobotAction>| {
+            let mut robot = models::robot::Robot::default();
+            robot.config.scheduler.rules = rules;
+            robot.config.scheduler.actions = actions;
+            return utils::policy::validate(&robot);
+        };
+
+        assert_eq!(
+            validate(vec![get_rule("managed", condition.clone())], vec![]),
+            Ok(())
+        );
+        assert_eq!(
+            validate(vec![get_rule(" ", condition.clone())], vec![]),
+            Err("Rule #1 needs a name".to_string())
+        );
+        assert_eq!(
+            validate(
+                vec![
+                    get_rule("managed", condition.clone()),
+                    get_rule("managed", condition.clone())
+                ],
+                vec![]
+            ),
+            Err("Rule \"managed\" is defined more than once".to_string())
+        );
+        assert_eq!(
+            validate(
+                vec![get_rule(
+                    "any",
+                    models::policy::PolicyCondition::Any(vec![])
+                )],
+                vec![]
+            ),
+            Err(
+                "Rule \"any\" is invalid ('all' and 'any' need at least one condition)".to_string()
+            )
+        );
+        assert_eq!(
+            validate(
+                vec![get_rule(
+                    "contains",
+                    models::policy::PolicyCondition::Contains {
+                        field: models::policy::PolicyTextField::Email,
+                        value: String::new(),
+                    }
+                )],
+                vec![]
+            ),
+            Err(
+                "Rule \"contains\" is invalid ('equals' and 'contains' need a non-empty value)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            validate(
+                vec![get_rule(
+                    "old",
+                    models::policy::PolicyCondition::PresenceOlderThan(-1)
+                )],
+                vec![]
+            ),
+            Err(
+                "Rule \"old\" is invalid ('presenceOlderThan' must be between 0 and 36500 days)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            validate(
+                vec![get_rule(
+                    "old",
+                    models::policy::PolicyCondition::PresenceOlderThan(36501)
+                )],
+                vec![]
+            ),
+            Err(
+                "Rule \"old\" is invalid ('presenceOlderThan' must be between 0 and 36500 days)"
+                    .to_string()
+            )
+        );
+
+        let mut nested = condition.clone();
+        for _ in 0..17 {
+            nested = models::policy::PolicyCondition::Not(Box::new(nested));
+        }
+        assert_eq!(
+            validate(vec![get_rule("nested", nested)], vec![]),
+            Err(
+                "Rule \"nested\" is invalid (conditions can't be nested deeper than 16 levels)"
+                    .to_string()
+            )
+        );
+
+        assert_eq!(
+            validate(
+                vec![get_rule("managed", condition.clone())],
+                vec![models::robot::RobotAction {
+                    reason: models::purge::PurgeReason::Custom("unmanaged".to_string()),
+                    action: models::purge::PurgeAction::Deactivate,
+                }]
+            ),
+            Err("Action refers to unknown rule \"unmanaged\"".to_string())
+        );
+    }
+}
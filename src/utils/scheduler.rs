@@ -3,6 +3,16 @@ use crate::*;
 // Import the necessary traits from the rayon crate
 use rayon::prelude::*;
 
+// The clients and configs a run shares between its robots
+#[derive(Clone, Copy)]
+struct RunContext<'a> {
+    scheduler_config: &'a configs::scheduler::Config,
+    notification_config: &'a configs::notification::Config,
+    reqwest: &'a clients::reqwest::Client,
+    mongodb: &'a clients::mongodb::Client,
+    rusoto: &'a clients::rusoto::Client,
+}
+
 async fn get_robots(
     mongodb: &clients::mongodb::Client,
     rusoto: &clients::rusoto::Client,
@@ -24,35 +34,1262 @@ fn get_active_robots(robots: &mut Vec<models::robot::Robot>) -> Vec<&mut models:
         .collect::<Vec<_>>();
 }
 
-async fn tick(
+async fn has_issue_activity(
+    source: &dyn clients::source::UserSource,
+    robot: &models::robot::Robot,
+    user: &models::jira::User,
+) -> bool {
+    if robot.config.scheduler.issue_activity <= 0 {
+        return false;
+    }
+    match source
+        .get_activity(robot, &user.id, robot.config.scheduler.issue_activity)
+        .await
+    {
+        Ok(total) => return total > 0,
+        Err(error) => {
+            // Without an answer we can't rule out activity, so the user is kept
+            log::warn!(
+                "Robot {:?} failed to check activity of user {:?} ({})",
+                robot.data.name,
+                user.display_name,
+                error
+            );
+            return true;
+        }
+    }
+}
+
+async fn add_purge_decision(
+    mongodb: &clients::mongodb::Client,
+    robot: &models::robot::Robot,
+    data: &models::purge::PurgeData,
+    protection: models::purge::PurgeProtection,
     now: chrono::DateTime<chrono::Utc>,
+) {
+    log::info!(
+        "Robot {:?} protection decision for user {:?}: {:?}",
+        robot.data.name,
+        data.user.display_name,
+        protection
+    );
+    if let Err(error) = mongodb
+        .add_purge_decision(&models::purge::PurgeDecision::new(data, protection, now))
+        .await
+    {
+        log::error!(
+            "Robot {:?} failed to record protection decision for user {:?} ({})",
+            robot.data.name,
+            data.user.display_name,
+            error
+        );
+    }
+}
+
+async fn expire_credential(
     reqwest: &clients::reqwest::Client,
     mongodb: &clients::mongodb::Client,
-    rusoto: &clients::rusoto::Client,
+    robot: &mut models::robot::Robot,
+    notification_config: &configs::notification::Config,
+    now: chrono::DateTime<chrono::Utc>,
+) {
+    if !robot.is_credential_expired() {
+        log::warn!("Robot {:?} credentials have expired", robot.data.name);
+        robot.data.credential_status = Some(models::robot::RobotCredentialStatus::new(
+            models::robot::RobotCredentialState::Expired,
+            now,
+        ));
+    }
+
+    // The owner is told once per expiry, retrying on later runs until it goes through
+    if robot
+        .data
+        .credential_status
+        .as_ref()
+        .is_some_and(|status| status.notified.is_some())
+    {
+        return;
+    }
+
+    let instructions = match robot.config.credential.user_source {
+        _ if robot.config.credential.platform_type == models::robot::RobotPlatform::JiraDataCenter => "Create a new personal access token in your Jira profile under Personal Access Tokens and update the robot's 'personalAccessToken' through PATCH /robots.",
+        _ if robot.config.credential.platform_type != models::robot::RobotPlatform::JiraCloud => "Create a new API token in https://id.atlassian.com/manage-profile/security/api-tokens and update the robot's 'platformApiKey' through PATCH /robots. If the robot also reads the organization directory, refresh its 'cloudSessionToken' or 'adminApiKey' as well.",
+        models::robot::RobotSource::AdminHub => "Log in to https://admin.atlassian.com with an organization admin account, copy the value of the 'cloud.session.token' cookie from your browser's developer tools and update the robot's 'cloudSessionToken' through PATCH /robots.",
+        models::robot::RobotSource::Organization => "Create a new API key in https://admin.atlassian.com under Settings > API keys and update the robot's 'adminApiKey' through PATCH /robots.",
+    };
+    let message = format!(
+        "Atlassian rejected the credentials of robot {}, so it can't see any users and won't queue or remove anyone until they are refreshed. {}",
+        robot.data.name, instructions
+    );
+
+    let emailed = robot.email_owner(
+        &notification_config.email,
+        &notification_config.password,
+        "[ALERT] Jira Purge Robot Credentials Expired",
+        &message,
+        &[],
+    );
+    let posted = match &notification_config.webhook {
+        Some(webhook) => reqwest
+            .post_webhook(
+                webhook,
+                &serde_json::json!({
+                    "event": "CREDENTIAL_EXPIRED",
+                    "robot": robot.data.id.unique.map(|id| id.to_string()),
+                    "name": robot.data.name,
+                    "owner": robot.config.credential.platform_email,
+                    "message": message,
+                }),
+            )
+            .await
+            .unwrap_or(false),
+        None => false,
+    };
+
+    if emailed || posted {
+        log::info!(
+            "Robot {:?} has notified {:?} about its expired credentials",
+            robot.data.name,
+            robot.config.credential.platform_email
+        );
+        if let Some(status) = robot.data.credential_status.as_mut() {
+            status.notified = Some(now);
+        }
+    } else {
+        log::error!(
+            "Robot {:?} failed to notify {:?} about its expired credentials",
+            robot.data.name,
+            robot.config.credential.platform_email
+        );
+    }
+
+    if let Err(error) = mongodb.set_robot_status(robot).await {
+        log::error!(
+            "Robot {:?} failed to save its credential status ({})",
+            robot.data.name,
+            error
+        );
+    }
+}
+
+async fn pause_robot(
+    reqwest: &clients::reqwest::Client,
+    mongodb: &clients::mongodb::Client,
+    robot: &mut models::robot::Robot,
+    notification_config: &configs::notification::Config,
+    removals: usize,
+    users: usize,
+    now: chrono::DateTime<chrono::Utc>,
+) {
+    let breaker = models::robot::RobotBreaker::new(removals, users, now);
+    robot.data.active = false;
+    robot.data.breaker = Some(breaker.clone());
+    if let Err(error) = mongodb.pause_robot(&robot.data.id, &breaker).await {
+        log::error!("Robot {:?} failed to pause ({})", robot.data.name, error);
+    }
+    log::warn!(
+        "Robot {:?} has been paused because it would remove {} of {} users",
+        robot.data.name,
+        removals,
+        users
+    );
+
+    // Besides the owner, the admins hear about it through the webhook and the escalation contacts
+    let message = format!(
+        "Robot {} would have removed {} of {} users in a single run, which exceeds its limits. The robot has been paused and won't remove anyone until it is resumed.",
+        robot.data.name, removals, users
+    );
+    let contacts = robot
+        .config
+        .scheduler
+        .escalation
+        .as_ref()
+        .map(|escalation| escalation.contacts.clone())
+        .unwrap_or_default();
+    let emailed = robot.email_owner(
+        &notification_config.email,
+        &notification_config.password,
+        "[ALERT] Jira Purge Robot Paused",
+        &message,
+        &contacts,
+    );
+    let posted = match &notification_config.webhook {
+        Some(webhook) => reqwest
+            .post_webhook(
+                webhook,
+                &serde_json::json!({
+                    "event": "ROBOT_PAUSED",
+                    "robot": robot.data.id.unique.map(|id| id.to_string()),
+                    "name": robot.data.name,
+                    "owner": robot.config.credential.platform_email,
+                    "removals": removals,
+                    "users": users,
+                    "message": message,
+                }),
+            )
+            .await
+            .unwrap_or(false),
+        None => false,
+    };
+
+    if !emailed {
+        log::error!(
+            "Robot {:?} failed to notify {:?} about the pause",
+            robot.data.name,
+            robot.config.credential.platform_email
+        );
+    }
+    if notification_config.webhook.is_some() && !posted {
+        log::error!(
+            "Robot {:?} failed to post its pause to the webhook",
+            robot.data.name
+        );
+    }
+}
+
+async fn is_purge_valid(
+    source: &dyn clients::source::UserSource,
+    robot: &models::robot::Robot,
+    user: &models::jira::User,
+    data: &models::purge::PurgeData,
+    role_holders: &Option<std::collections::HashSet<String>>,
+    memberships: &models::jira::GroupMemberships,
+    now: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    // System accounts are never purged
+    if user.system {
+        return false;
+    }
+
+    // Users who joined an excluded group (or left the included ones) are no longer purged
+    if !robot.is_user_purgeable(&user.id, memberships) {
+        return false;
+    }
+
+    let mut remove = false;
+    for reason in &data.reasons {
+        match reason {
+            models::purge::PurgeReason::ActiveStatus => {
+                if !user.active {
+                    remove = true;
+                }
+            }
+            models::purge::PurgeReason::DuplicateEmail => {
+                if user.email == data.user.email {
+                    remove = true;
+                }
+            }
+            models::purge::PurgeReason::DuplicateName => {
+                if user.display_name == data.user.display_name {
+                    remove = true;
+                }
+            }
+            models::purge::PurgeReason::LastActive => {
+                let presence = user.get_available_presence();
+                if presence.is_some()
+                    && presence <= data.user.presence
+                    && !has_issue_activity(source, robot, user).await
+                {
+                    remove = true;
+                }
+            }
+            models::purge::PurgeReason::StaleInvitation => {
+                if user.get_pending_invitation().is_some() {
+                    remove = true;
+                }
+            }
+            models::purge::PurgeReason::NoProjectRole => {
+                if role_holders
+                    .as_ref()
+                    .is_some_and(|holders| !holders.contains(&user.id))
+                {
+                    remove = true;
+                }
+            }
+            models::purge::PurgeReason::Custom(name) => {
+                if utils::policy::is_reason_valid(robot, user, name, now) {
+                    remove = true;
+                }
+            }
+        }
+        if remove {
+            break;
+        }
+    }
+
+    return remove;
+}
+
+async fn finish_purge(
+    mongodb: &clients::mongodb::Client,
+    robot: &models::robot::Robot,
+    data: &mut models::purge::PurgeData,
+    log: models::purge::PurgeLog,
+) {
+    // Removed is recorded first, a crash before the queue entry is gone gets finished on startup
+    data.state = models::purge::PurgeState::Removed;
+    data.error = None;
+    if let Err(error) = mongodb.patch_purge_user(data).await {
+        log::error!(
+            "Robot {:?} failed to record the removal of user {:?} ({})",
+            robot.data.name,
+            data.user.display_name,
+            error
+        );
+        return;
+    }
+    if let Err(error) = mongodb.add_purge_log(&log).await {
+        log::error!(
+            "Robot {:?} failed to log user {:?} removal ({})",
+            robot.data.name,
+            data.user.display_name,
+            error
+        );
+        return;
+    }
+    if let Err(error) = mongodb.delete_purge_user(data).await {
+        log::error!(
+            "Robot {:?} failed to remove user {:?} from purging queue ({})",
+            robot.data.name,
+            data.user.display_name,
+            error
+        );
+    }
+}
+
+async fn fail_purge(
+    mongodb: &clients::mongodb::Client,
+    scheduler_config: &configs::scheduler::Config,
+    notification_config: &configs::notification::Config,
+    robot: &models::robot::Robot,
+    data: &mut models::purge::PurgeData,
+    error: String,
+) {
+    data.state = models::purge::PurgeState::Failed;
+    data.error = Some(error.clone());
+    if let Err(error) = mongodb.patch_purge_user(data).await {
+        log::error!(
+            "Robot {:?} failed to record the failed removal of user {:?} ({})",
+            robot.data.name,
+            data.user.display_name,
+            error
+        );
+    }
+
+    if data.attempts < scheduler_config.purge_attempts {
+        log::warn!(
+            "Robot {:?} failed to purge user {:?}, retrying on the next run ({})",
+            robot.data.name,
+            data.user.display_name,
+            error
+        );
+        return;
+    }
+
+    // Out of attempts, the user stays queued as failed until the owner steps in
+    log::error!(
+        "Robot {:?} gave up purging user {:?} after {} attempts ({})",
+        robot.data.name,
+        data.user.display_name,
+        data.attempts,
+        error
+    );
+    if !robot.email_owner(
+        &notification_config.email,
+        &notification_config.password,
+        "[ALERT] Jira Purge Failed",
+        &format!(
+            "Robot {} failed to purge user {} ({}) after {} attempts: {}. The user stays in the queue and won't be retried.",
+            robot.data.name, data.user.display_name, data.user.email, data.attempts, error
+        ),
+        &[],
+    ) {
+        log::error!(
+            "Robot {:?} failed to notify {:?} about the failed removal",
+            robot.data.name,
+            robot.config.credential.platform_email
+        );
+    }
+}
+
+fn load_managers(
+    notification_config: &configs::notification::Config,
+    escalation: &Option<models::robot::RobotEscalation>,
+) -> Result<Option<std::collections::HashMap<String, String>>, Box<dyn std::error::Error>> {
+    let path = match escalation
+        .as_ref()
+        .and_then(|escalation| escalation.managers.as_ref())
+    {
+        Some(models::robot::RobotManagerSource::File { path }) => path,
+        _ => return Ok(None),
+    };
+
+    // Robots only name a file inside MANAGERS_DIR, so their config can't read anything else on the server
+    let directory = notification_config
+        .managers_dir
+        .as_ref()
+        .ok_or("MANAGERS_DIR isn't set")?;
+    let mut components = std::path::Path::new(path).components();
+    if !matches!(components.next(), Some(std::path::Component::Normal(_)))
+        || components.next().is_some()
+    {
+        return Err(format!("{:?} isn't a file name", path).into());
+    }
+
+    // The file maps account ids or emails to the manager's email
+    return Ok(Some(serde_yaml::from_str(&std::fs::read_to_string(
+        std::path::Path::new(directory).join(path),
+    )?)?));
+}
+
+fn get_escalation_recipients(
+    escalation: &models::robot::RobotEscalation,
+    managers: &Option<std::collections::HashMap<String, String>>,
+    user: &models::jira::User,
+) -> Vec<String> {
+    let manager = match &escalation.managers {
+        Some(models::robot::RobotManagerSource::File { .. }) => {
+            managers.as_ref().and_then(|managers| {
+                return managers
+                    .get(&user.id)
+                    .or(managers
+                        .iter()
+                        .find(|(key, _manager)| key.eq_ignore_ascii_case(&user.email))
+                        .map(|(_key, manager)| manager))
+                    .cloned();
+            })
+        }
+        Some(models::robot::RobotManagerSource::Attribute { field }) => {
+            utils::policy::get_text(user, *field)
+                .filter(|manager| manager.contains('@'))
+                .cloned()
+        }
+        None => None,
+    };
+
+    // Without a known manager the robot's contacts hear about it instead
+    return match manager {
+        Some(manager) => vec![manager],
+        None => escalation.contacts.clone(),
+    };
+}
+
+async fn escalate_user(
+    mongodb: &clients::mongodb::Client,
     notification_config: &configs::notification::Config,
+    robot: &models::robot::Robot,
+    user: &models::jira::User,
+    data: &mut models::purge::PurgeData,
+    managers: &Option<std::collections::HashMap<String, String>>,
+    now: chrono::DateTime<chrono::Utc>,
+) {
+    let escalation = match &robot.config.scheduler.escalation {
+        Some(escalation) => escalation,
+        None => return,
+    };
+
+    let recipients = get_escalation_recipients(escalation, managers, user);
+    if recipients.is_empty() {
+        log::warn!(
+            "Robot {:?} has no one to escalate user {:?} to",
+            robot.data.name,
+            user.display_name
+        );
+        return;
+    }
+
+    // Only a delivered escalation is recorded, so a failed one is tried again on the next reminder
+    if !data.email_managers(
+        &notification_config.email,
+        &notification_config.password,
+        &recipients,
+        &robot.config.credential.platform_email,
+    ) {
+        log::error!(
+            "Robot {:?} failed to escalate user {:?} to {:?}",
+            robot.data.name,
+            user.display_name,
+            recipients
+        );
+        return;
+    }
+
+    data.escalations.push(models::purge::PurgeEscalation {
+        recipients: recipients.clone(),
+        time: now,
+    });
+    match mongodb.patch_purge_user(data).await {
+        Ok(_result) => {
+            log::info!(
+                "Robot {:?} has escalated user {:?} to {:?}",
+                robot.data.name,
+                user.display_name,
+                recipients
+            );
+        }
+        Err(error) => {
+            log::error!(
+                "Robot {:?} failed to record the escalation of user {:?} ({})",
+                robot.data.name,
+                user.display_name,
+                error
+            );
+        }
+    }
+}
+
+async fn remove_user(
+    context: &RunContext<'_>,
+    source: &dyn clients::source::UserSource,
+    robot: &models::robot::Robot,
+    user: &models::jira::User,
+    data: &mut models::purge::PurgeData,
+    now: chrono::DateTime<chrono::Utc>,
+) {
+    let RunContext {
+        scheduler_config,
+        notification_config,
+        mongodb,
+        ..
+    } = *context;
+
+    // A retry repeats the action it started with, even when the robot's actions changed since
+    let action = data
+        .action
+        .clone()
+        .unwrap_or_else(|| robot.get_purge_action(&data.reasons));
+
+    // Removing is recorded before calling Atlassian, so a crash midway is noticed on startup
+    let state = data.state;
+    data.state = models::purge::PurgeState::Removing;
+    data.action = Some(action.clone());
+    data.attempts += 1;
+    match mongodb.start_purge_user(data, state).await {
+        Ok(Some(_data)) => {}
+        Ok(None) => {
+            // The entry changed since it was loaded, e.g. the user kept their account
+            log::warn!(
+                "Robot {:?} skipped purging user {:?}, their queue entry changed",
+                robot.data.name,
+                data.user.display_name
+            );
+            return;
+        }
+        Err(error) => {
+            log::error!(
+                "Robot {:?} failed to start removing user {:?} ({})",
+                robot.data.name,
+                data.user.display_name,
+                error
+            );
+            return;
+        }
+    }
+
+    match source.purge_user(robot, data, &action).await {
+        Ok(true) => {
+            log::info!(
+                "Robot {:?} has purged user {:?} ({:?}) because of the following reason(s): {:?}",
+                robot.data.name,
+                user.display_name,
+                action,
+                data.reasons
+            );
+            let log = models::purge::PurgeLog::new(robot, user, data, action, now);
+            finish_purge(mongodb, robot, data, log).await;
+        }
+        Ok(false) => {
+            fail_purge(
+                mongodb,
+                scheduler_config,
+                notification_config,
+                robot,
+                data,
+                format!("{:?} was rejected", action),
+            )
+            .await;
+        }
+        Err(error) => {
+            fail_purge(
+                mongodb,
+                scheduler_config,
+                notification_config,
+                robot,
+                data,
+                error.to_string(),
+            )
+            .await;
+        }
+    }
+}
+
+async fn reconcile(
+    mongodb: &clients::mongodb::Client,
+    rusoto: &clients::rusoto::Client,
+    scheduler_config: &configs::scheduler::Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let now = chrono::Utc::now();
+    let robots = get_robots(mongodb, rusoto).await?;
+    let purge_data = mongodb.get_purge_users().await?;
+    for robot in &robots {
+        let mut interrupted = purge_data
+            .iter()
+            .filter(|data| data.robot.id == robot.data.id.unique.unwrap())
+            .filter(|data| {
+                return matches!(
+                    data.state,
+                    models::purge::PurgeState::Removing | models::purge::PurgeState::Removed
+                );
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if interrupted.is_empty() {
+            continue;
+        }
+
+        // A robot another instance is running is reconciled by that instance
+        let lock = get_lock_name(robot);
+        if !mongodb
+            .acquire_lock(
+                &lock,
+                &scheduler_config.instance_id,
+                now,
+                scheduler_config.lease,
+            )
+            .await?
+        {
+            continue;
+        }
+        for data in interrupted.iter_mut() {
+            if data.state == models::purge::PurgeState::Removed {
+                let log = models::purge::PurgeLog::from_purge(robot, data, now);
+                finish_purge(mongodb, robot, data, log).await;
+                continue;
+            }
+
+            // Atlassian may or may not have removed the user, the next run retries or finds them gone
+            log::warn!(
+                "Robot {:?} was interrupted while purging user {:?}",
+                robot.data.name,
+                data.user.display_name
+            );
+            data.state = models::purge::PurgeState::Failed;
+            data.error = Some("Interrupted before the removal was confirmed".to_string());
+            mongodb.patch_purge_user(data).await?;
+        }
+        mongodb
+            .release_lock(&lock, &scheduler_config.instance_id)
+            .await?;
+    }
+    return Ok(());
+}
+
+fn get_identity(user: &models::jira::User) -> String {
+    return format!(
+        "{}\n{}\n{}",
+        user.display_name,
+        user.email,
+        user.created
+            .map(|created| created.to_rfc3339())
+            .unwrap_or_default()
+    );
+}
+
+fn get_scoring(robot: &models::robot::Robot) -> String {
+    return format!(
+        "{}:{}:{}:{}",
+        robot.config.scheduler.check_double_name,
+        robot.config.scheduler.double_name_threshold,
+        robot.config.scheduler.check_double_email,
+        robot.config.scheduler.double_email_threshold
+    );
+}
+
+fn compare_users(
+    robot: &models::robot::Robot,
+    user: &models::jira::User,
+    other_user: &models::jira::User,
+) -> Option<(
+    String,
+    String,
+    std::collections::BTreeSet<models::purge::PurgeReason>,
+)> {
+    let mut reasons = std::collections::BTreeSet::new();
+    if robot.config.scheduler.check_double_name {
+        let similarity =
+            strsim::normalized_damerau_levenshtein(&user.display_name, &other_user.display_name)
+                * 100.0;
+        if similarity >= robot.config.scheduler.double_name_threshold.into() {
+            reasons.insert(models::purge::PurgeReason::DuplicateName);
+        }
+    }
+    // Users without an email (e.g. Bitbucket members) aren't duplicates of each other
+    if robot.config.scheduler.check_double_email
+        && !user.email.is_empty()
+        && !other_user.email.is_empty()
+    {
+        let similarity =
+            strsim::normalized_damerau_levenshtein(&user.email, &other_user.email) * 100.0;
+        if similarity >= robot.config.scheduler.double_email_threshold.into() {
+            reasons.insert(models::purge::PurgeReason::DuplicateEmail);
+        }
+    }
+    if reasons.is_empty() {
+        return None;
+    }
+
+    // The newer account of the two is the duplicate, without both creation dates there's no telling which
+    let (created, other_created) = match (user.created, other_user.created) {
+        (Some(created), Some(other_created)) => (created, other_created),
+        _ => return None,
+    };
+    if (created, &user.id) <= (other_created, &other_user.id) {
+        return Some((other_user.id.clone(), user.id.clone(), reasons));
+    }
+    return Some((user.id.clone(), other_user.id.clone(), reasons));
+}
+
+fn score_duplicates(
+    robot: &models::robot::Robot,
+    users: &Vec<models::jira::User>,
+    previous: &std::collections::HashMap<String, models::snapshot::SnapshotUser>,
+    rescore: bool,
+) -> std::collections::HashMap<String, models::snapshot::Duplicates> {
+    // Only users that are new or whose name, email or creation changed since they were scored are compared again
+    let dirty = &users
+        .iter()
+        .filter(|user| {
+            return rescore
+                || previous.get(&user.id).is_none_or(|snapshot| {
+                    return snapshot.scored.as_ref() != Some(&get_identity(user));
+                });
+        })
+        .map(|user| user.id.clone())
+        .collect::<std::collections::HashSet<_>>();
+    let current = users
+        .iter()
+        .map(|user| &user.id)
+        .collect::<std::collections::HashSet<_>>();
+
+    // Pairs of unchanged users keep their previous score
+    let mut duplicates = users
+        .iter()
+        .filter(|user| !dirty.contains(&user.id))
+        .map(|user| {
+            let kept = previous[&user.id]
+                .duplicates
+                .iter()
+                .filter(|(other, _reasons)| current.contains(other) && !dirty.contains(*other))
+                .map(|(other, reasons)| (other.clone(), reasons.clone()))
+                .collect::<models::snapshot::Duplicates>();
+            return (user.id.clone(), kept);
+        })
+        .collect::<std::collections::HashMap<_, _>>();
+
+    // Every changed user is compared with everyone, a pair of changed users only once
+    let pairs = users
+        .par_iter()
+        .filter(|user| dirty.contains(&user.id))
+        .flat_map(|user| {
+            return users
+                .par_iter()
+                .filter(move |other_user| {
+                    return other_user.id != user.id
+                        && !(dirty.contains(&other_user.id) && other_user.id < user.id);
+                })
+                .filter_map(move |other_user| compare_users(robot, user, other_user));
+        })
+        .collect::<Vec<_>>();
+    for (user, other_user, reasons) in pairs {
+        duplicates
+            .entry(user)
+            .or_default()
+            .insert(other_user, reasons);
+    }
+    return duplicates;
+}
+
+fn analyze_users(
+    robot: &models::robot::Robot,
+    users: &Vec<models::jira::User>,
+    duplicates: &std::collections::HashMap<String, models::snapshot::Duplicates>,
+    role_holders: &Option<std::collections::HashSet<String>>,
+    memberships: &models::jira::GroupMemberships,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<(
+    String,
+    std::collections::HashSet<models::purge::PurgeReason>,
+)> {
+    // Get all jira users with duplicate attributes
+    let duplicate_users = users
+        .par_iter()
+        .filter_map(|user| {
+            let reasons = duplicates
+                .get(&user.id)?
+                .values()
+                .flatten()
+                .cloned()
+                .collect::<std::collections::HashSet<_>>();
+            if reasons.is_empty() {
+                return None;
+            }
+            return Some((user, reasons));
+        })
+        .collect::<Vec<_>>();
+
+    // Get all inactive jira users
+    let inactive_users = users
+        .par_iter()
+        .flat_map(|user| {
+            let mut reasons: std::collections::HashSet<models::purge::PurgeReason> =
+                std::collections::HashSet::new();
+
+            // Check active status
+            if robot.config.scheduler.check_active_status && !user.active {
+                reasons.insert(models::purge::PurgeReason::ActiveStatus);
+            }
+
+            // Check presence
+            if robot.config.scheduler.last_active > 0
+                && user.get_available_presence().is_some_and(|presence| {
+                    return presence
+                        <= now - chrono::Duration::days(robot.config.scheduler.last_active);
+                })
+            {
+                reasons.insert(models::purge::PurgeReason::LastActive);
+            }
+
+            // Check pending invitation
+            if robot.config.scheduler.stale_invitation > 0 {
+                if let Some(invitation) = user.get_pending_invitation() {
+                    if invitation.invited_at
+                        <= now - chrono::Duration::days(robot.config.scheduler.stale_invitation)
+                    {
+                        reasons.insert(models::purge::PurgeReason::StaleInvitation);
+                    }
+                }
+            }
+
+            // Check project roles
+            if let Some(holders) = role_holders {
+                if !holders.contains(&user.id) {
+                    reasons.insert(models::purge::PurgeReason::NoProjectRole);
+                }
+            }
+
+            // Check custom rules
+            reasons.extend(utils::policy::get_reasons(robot, user, now));
+
+            if reasons.is_empty() {
+                return None;
+            }
+
+            return Some((user, reasons));
+        })
+        .collect::<Vec<_>>();
+
+    let mut filtered_users: Vec<(
+        &models::jira::User,
+        std::collections::HashSet<models::purge::PurgeReason>,
+    )> = Vec::new();
+    filtered_users.extend(duplicate_users);
+    filtered_users.extend(inactive_users);
+
+    // Combine or remove duplicate users data since we seperate the loop between duplicate and inactivity
+    return filtered_users
+        .into_iter()
+        .fold(
+            std::collections::HashMap::<
+                String,
+                (
+                    &models::jira::User,
+                    std::collections::HashSet<models::purge::PurgeReason>,
+                ),
+            >::new(),
+            |mut data, (user, reason)| {
+                data.entry(user.id.clone())
+                    .and_modify(|(_existing_user, existing_reason)| {
+                        // If a user with the same ID already exists, append their reason
+                        existing_reason.extend(reason.clone());
+                    })
+                    .or_insert((user, reason));
+                return data;
+            },
+        )
+        .into_values()
+        .filter(|(user, _reasons)| robot.is_user_purgeable(&user.id, memberships))
+        .map(|(user, reasons)| (user.id.clone(), reasons))
+        .collect::<Vec<_>>();
+}
+
+async fn save_snapshot(
+    mongodb: &clients::mongodb::Client,
+    robot: &models::robot::Robot,
+    users: &Vec<models::jira::User>,
+    previous: &std::collections::HashMap<String, models::snapshot::SnapshotUser>,
+    duplicates: Option<std::collections::HashMap<String, models::snapshot::Duplicates>>,
+    snapshot: models::snapshot::Snapshot,
+    previous_version: Option<mongodb::bson::oid::ObjectId>,
+) {
+    let now = snapshot.fetched;
+    let mut snapshot_users = vec![];
+    for user in users {
+        let mut snapshot_user = models::snapshot::SnapshotUser::new(robot, user, now);
+        snapshot_user.version = snapshot.version;
+        match &duplicates {
+            Some(duplicates) => {
+                snapshot_user.duplicates = duplicates.get(&user.id).cloned().unwrap_or_default();
+                snapshot_user.scored = Some(get_identity(user));
+            }
+            None => {
+                if let Some(previous) = previous.get(&user.id) {
+                    snapshot_user.duplicates = previous.duplicates.clone();
+                    snapshot_user.scored = previous.scored.clone();
+                }
+            }
+        }
+        snapshot_users.push(snapshot_user);
+    }
+
+    // The users are written under a new version that readers only see once the snapshot switches to it,
+    // so a failed run keeps the previous snapshot whole
+    if let Err(error) = mongodb.set_snapshot_users(&snapshot_users).await {
+        log::error!(
+            "Robot {:?} failed to save {} users to its snapshot ({})",
+            robot.data.name,
+            snapshot_users.len(),
+            error
+        );
+        return;
+    }
+    if let Err(error) = mongodb.set_snapshot(&snapshot).await {
+        log::error!(
+            "Robot {:?} failed to save its snapshot ({})",
+            robot.data.name,
+            error
+        );
+        return;
+    }
+
+    // The previous version stays until the next run for readers that loaded the snapshot before the switch
+    if let Err(error) = mongodb
+        .delete_snapshot_users(&robot.data.id, &[previous_version, snapshot.version])
+        .await
+    {
+        log::error!(
+            "Robot {:?} failed to remove old versions of its snapshot ({})",
+            robot.data.name,
+            error
+        );
+    }
+}
+
+async fn wait_for_deadline(
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+    deadline: chrono::Duration,
+) {
+    // The deadline only starts counting once a shutdown is requested
+    while !*shutdown.borrow() {
+        if shutdown.changed().await.is_err() {
+            return std::future::pending().await;
+        }
+    }
+    actix_rt::time::sleep(deadline.to_std().unwrap_or_default()).await;
+}
+
+async fn interrupt_robot(
+    mongodb: &clients::mongodb::Client,
+    robot: &mut models::robot::Robot,
+    last_run: Option<chrono::DateTime<chrono::Utc>>,
+    interrupted: &std::sync::Mutex<Vec<String>>,
+) {
+    // Without this run on record the robot is due again as soon as the scheduler is back
+    log::warn!("Robot {:?} stopped early for shutdown", robot.data.name);
+    robot.data.last_run = last_run;
+    if let Err(error) = mongodb.set_robot_last_run(&robot.data.id, last_run).await {
+        log::error!(
+            "Robot {:?} failed to restore its last run ({})",
+            robot.data.name,
+            error
+        );
+    }
+    interrupted.lock().unwrap().push(robot.data.name.clone());
+}
+
+fn get_lock_name(robot: &models::robot::Robot) -> String {
+    return format!("robot:{}", robot.data.id.unique.unwrap());
+}
+
+async fn claim_robot(
+    mongodb: &clients::mongodb::Client,
+    scheduler_config: &configs::scheduler::Config,
+    robot: &models::robot::Robot,
+) -> bool {
+    let lock = get_lock_name(robot);
+    match mongodb
+        .acquire_lock(
+            &lock,
+            &scheduler_config.instance_id,
+            chrono::Utc::now(),
+            scheduler_config.lease,
+        )
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            log::info!(
+                "Robot {:?} is being processed by another instance, skipping",
+                robot.data.name
+            );
+            return false;
+        }
+        Err(error) => {
+            log::error!(
+                "Robot {:?} failed to acquire its lock ({})",
+                robot.data.name,
+                error
+            );
+            return false;
+        }
+    }
+
+    // Another instance may have finished the robot between loading and locking it
+    let claimed = match mongodb.get_robot(&robot.data.id).await {
+        Ok(Some(data)) => data.last_run == robot.data.last_run,
+        Ok(None) => false,
+        Err(error) => {
+            log::error!(
+                "Robot {:?} failed to reload its last run ({})",
+                robot.data.name,
+                error
+            );
+            false
+        }
+    };
+    if !claimed {
+        if let Err(error) = mongodb
+            .release_lock(&lock, &scheduler_config.instance_id)
+            .await
+        {
+            log::error!(
+                "Robot {:?} failed to release its lock ({})",
+                robot.data.name,
+                error
+            );
+        }
+    }
+    return claimed;
+}
+
+async fn keep_lock<T, F: std::future::Future<Output = T>>(
+    mongodb: &clients::mongodb::Client,
+    scheduler_config: &configs::scheduler::Config,
+    lock: &String,
+    future: F,
+) -> Option<T> {
+    tokio::pin!(future);
+    let mut renewed = chrono::Utc::now();
+    loop {
+        // Renew the lease while the future runs, and stop it once another instance may have taken over
+        tokio::select! {
+            output = &mut future => return Some(output),
+            _ = actix_rt::time::sleep((scheduler_config.lease / 3).to_std().unwrap_or_default()) => {
+                let now = chrono::Utc::now();
+                match mongodb.renew_lock(lock, &scheduler_config.instance_id, now, scheduler_config.lease).await {
+                    Ok(true) => renewed = now,
+                    Ok(false) => {
+                        log::warn!("Lost lock {:?} to another instance, stopping", lock);
+                        return None;
+                    }
+                    Err(error) => {
+                        log::warn!("Failed to renew lock {:?} ({})", lock, error);
+                        if now - renewed >= scheduler_config.lease {
+                            log::warn!("Lock {:?} expired, stopping", lock);
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn hold_lock<F: std::future::Future<Output = ()>>(
+    mongodb: &clients::mongodb::Client,
+    scheduler_config: &configs::scheduler::Config,
+    lock: String,
+    future: F,
+) {
+    if keep_lock(mongodb, scheduler_config, &lock, future)
+        .await
+        .is_none()
+    {
+        return;
+    }
+    if let Err(error) = mongodb
+        .release_lock(&lock, &scheduler_config.instance_id)
+        .await
+    {
+        log::error!("Failed to release lock {:?} ({})", lock, error);
+    }
+}
+
+async fn tick(
+    now: chrono::DateTime<chrono::Utc>,
+    global: bool,
+    context: &RunContext<'_>,
+    shutdown: &tokio::sync::watch::Receiver<bool>,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, Box<dyn std::error::Error>> {
+    let RunContext {
+        scheduler_config,
+        notification_config,
+        reqwest,
+        mongodb,
+        rusoto,
+    } = *context;
+
     // Call the `get_robots` method on the `Client` object to retrieve a list of robots.
     let mut robots = get_robots(mongodb, rusoto).await?;
 
-    // Filter inactive robots.
-    let mut active_robots = get_active_robots(&mut robots);
+    // Filter inactive robots, robots with a cron of their own only run when it's due, the others on the global schedule.
+    let mut active_robots = get_active_robots(&mut robots)
+        .into_iter()
+        .filter(|robot| {
+            robot
+                .get_next_run()
+                .map_or(global, |next_run| next_run <= now)
+        })
+        .collect::<Vec<_>>();
+    let locks = active_robots
+        .iter()
+        .map(|robot| (get_lock_name(robot), robot.data.name.clone()))
+        .collect::<Vec<_>>();
+    let last_runs = active_robots
+        .iter()
+        .map(|robot| robot.data.last_run)
+        .collect::<Vec<_>>();
+
+    // Robots stop at safe points once a shutdown is requested, between users and before removing anyone
+    let interrupted_robots = std::sync::Mutex::new(Vec::new());
+    let interrupted = &interrupted_robots;
+    let running_robots = std::sync::Mutex::new(std::collections::BTreeSet::new());
+    let running = &running_robots;
 
     // Create a vector to hold async tasks that we'll run concurrently
     let futures = active_robots
         .iter_mut()
         .map(|robot| async move {
-            // Get all jira users
-            let mut users = reqwest
-                .get_jira_users(&robot.config.credential.cloud_session_token)
-                .await;
+            // Robots that haven't started yet wait for the next start
+            if *shutdown.borrow() {
+                return;
+            }
+
+            // Only one instance processes a robot at a time
+            if !claim_robot(mongodb, scheduler_config, robot).await {
+                return;
+            }
+            running.lock().unwrap().insert(get_lock_name(robot));
+            let last_run = robot.data.last_run;
+
+            // Record the run up front, a robot that fails halfway waits until it's due again
+            robot.data.last_run = Some(now);
+            if let Err(error) = mongodb.set_robot_last_run(&robot.data.id, Some(now)).await {
+                log::error!("Robot {:?} failed to record its run ({})", robot.data.name, error);
+            }
+
+            // Listing, activity and removal all go through the robot's platform
+            let source = clients::source::new(reqwest, robot);
+
+            // Get all jira users, acting on an incomplete list would purge everyone who's missing
+            let mut users = match source.get_users(robot).await {
+                Ok(users) => users,
+                Err(errors::atlassian::Error::Unauthorized) => {
+                    expire_credential(reqwest, mongodb, robot, notification_config, now).await;
+                    return;
+                }
+                Err(error) => {
+                    log::error!("Robot {:?} failed to retrieve users, skipping this run ({})", robot.data.name, error);
+                    return;
+                }
+            };
+
+            // Credentials that work again were refreshed by the owner
+            if robot.is_credential_expired() {
+                robot.data.credential_status = Some(models::robot::RobotCredentialStatus::new(
+                    models::robot::RobotCredentialState::Valid,
+                    now,
+                ));
+                if let Err(error) = mongodb.set_robot_status(robot).await {
+                    log::error!("Robot {:?} failed to save its credential status ({})", robot.data.name, error);
+                }
+                log::info!("Robot {:?} credentials are valid again", robot.data.name);
+            }
 
             // Sort all jira users base on created key
             users.sort_by_key(|user| user.created);
 
+            // Get the members of every group the robot includes or excludes, once per tick
+            let memberships = match source.get_group_memberships(robot).await {
+                Ok(memberships) => memberships,
+                Err(error) => {
+                    log::error!("Robot {:?} failed to retrieve group memberships ({})", robot.data.name, error);
+                    return;
+                }
+            };
+
+            // Get every user holding a project role anywhere on the site
+            let role_holders = if robot.config.scheduler.check_project_role {
+                match source.get_role_holders(robot).await {
+                    Ok(holders) => holders,
+                    Err(error) => {
+                        log::error!("Robot {:?} failed to retrieve project roles ({})", robot.data.name, error);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            // Compare the users with the robot's last snapshot
+            let snapshot = match mongodb.get_snapshot(&robot.data.id).await {
+                Ok(snapshot) => snapshot,
+                Err(error) => {
+                    log::error!("Robot {:?} failed to retrieve its snapshot ({})", robot.data.name, error);
+                    None
+                }
+            };
+            let previous = match snapshot.as_ref() {
+                Some(snapshot) => mongodb.get_snapshot_users(snapshot).await,
+                None => Ok(Vec::new()),
+            };
+            let previous = match previous {
+                Ok(previous) => previous,
+                Err(error) => {
+                    log::error!("Robot {:?} failed to retrieve its snapshot users ({})", robot.data.name, error);
+                    Vec::new()
+                }
+            };
+            let previous = std::sync::Arc::new(
+                previous
+                    .into_iter()
+                    .map(|snapshot| (snapshot.user.id.clone(), snapshot))
+                    .collect::<std::collections::HashMap<_, _>>(),
+            );
+            let diff = models::snapshot::SnapshotDiff::new(&previous, &users);
+            log::info!("Robot {:?} found {} new, {} changed and {} removed users", robot.data.name, diff.added.len(), diff.changed.len(), diff.removed.len());
+            let previous_version = snapshot.as_ref().and_then(|snapshot| snapshot.version);
+            let mut scoring = snapshot.map(|snapshot| snapshot.scoring);
+            let mut duplicates = None;
+
+            if *shutdown.borrow() {
+                interrupt_robot(mongodb, robot, last_run, interrupted).await;
+                return;
+            }
+
             if !robot.is_updated(now) {
                 // Change the robot status to become updated
                 robot.data.modified = Some(now);
-                if let Err(error) = mongodb.patch_robot(robot).await {
+                if let Err(error) = mongodb.set_robot_status(robot).await {
                     log::error!("Failed to set robot status ({})", error);
                 }
 
@@ -77,116 +1314,130 @@ async fn tick(
                     }
                 }
 
-                // Get all jira users with duplicate attributes
-                let duplicate_users = users
-                    .par_iter()
-                    .enumerate()
-                    .flat_map(|(i, user)| {
-                        return users
-                            .par_iter()
-                            .enumerate()
-                            .skip(i + 1) // start from the next element
-                            .filter_map(|(_j, other_user)| {
-                                let mut reasons: std::collections::HashSet<models::purge::PurgeReason> =
-                                    std::collections::HashSet::new();
-                                if robot.config.scheduler.check_double_name {
-                                    let similarity = strsim::normalized_damerau_levenshtein(
-                                        &user.display_name,
-                                        &other_user.display_name,
-                                    ) * 100.0;
-                                    if similarity >= robot.config.scheduler.double_name_threshold.into() {
-                                        reasons.insert(models::purge::PurgeReason::DuplicateName);
-                                    }
+                // Comparing pairs of users is CPU heavy, so the analysis runs on a blocking thread instead of the runtime
+                // Duplicates are only scored again for users that changed since the last snapshot, or all of them when the thresholds changed
+                let rescore = scoring.as_ref() != Some(&get_scoring(robot));
+                let analysis = tokio::task::spawn_blocking({
+                    let robot = models::robot::Robot::clone(robot);
+                    let users = users.clone();
+                    let previous = previous.clone();
+                    let role_holders = role_holders.clone();
+                    let memberships = memberships.clone();
+                    move || {
+                        let duplicates = score_duplicates(&robot, &users, &previous, rescore);
+                        let analysis = analyze_users(&robot, &users, &duplicates, &role_holders, &memberships, now);
+                        return (analysis, duplicates);
+                    }
+                })
+                .await;
+                let users_by_id = users
+                    .iter()
+                    .map(|user| (user.id.clone(), user))
+                    .collect::<std::collections::HashMap<_, _>>();
+                let mut unique_filtered_users = match analysis {
+                    Ok((analysis, scores)) => {
+                        scoring = Some(get_scoring(robot));
+                        duplicates = Some(scores);
+                        analysis
+                            .into_iter()
+                            .filter_map(|(id, reasons)| users_by_id.get(&id).map(|user| (*user, reasons)))
+                            .collect::<Vec<_>>()
+                    },
+                    Err(error) => {
+                        log::error!("Robot {:?} failed to analyze users ({})", robot.data.name, error);
+                        return;
+                    }
+                };
+
+                // Recent issue activity is proof of life, even when presence says otherwise
+                for (user, reasons) in unique_filtered_users.iter_mut() {
+                    if reasons.contains(&models::purge::PurgeReason::LastActive)
+                        && has_issue_activity(source.as_ref(), robot, user).await
+                    {
+                        reasons.remove(&models::purge::PurgeReason::LastActive);
+                    }
+                }
+
+                // Give stale invitations one more chance by re-sending them before queueing
+                if robot.config.scheduler.resend_invitation {
+                    match mongodb.get_purge_invitations(&robot.data.id).await {
+                        Ok(invitations) => {
+                            for (user, reasons) in unique_filtered_users.iter_mut() {
+                                if !reasons.contains(&models::purge::PurgeReason::StaleInvitation) {
+                                    continue;
                                 }
-                                if robot.config.scheduler.check_double_email {
-                                    let similarity = strsim::normalized_damerau_levenshtein(
-                                        &user.email,
-                                        &other_user.email,
-                                    ) * 100.0;
-                                    if similarity >= robot.config.scheduler.double_email_threshold.into()  {
-                                        reasons.insert(models::purge::PurgeReason::DuplicateEmail);
+
+                                // A re-sent invitation is only stale once it's been pending as long again
+                                let resent = invitations.iter().filter(|invitation| invitation.user == user.id).map(|invitation| invitation.time).max();
+                                if let Some(resent) = resent {
+                                    if resent > now - chrono::Duration::days(robot.config.scheduler.stale_invitation) {
+                                        reasons.remove(&models::purge::PurgeReason::StaleInvitation);
                                     }
+                                    continue;
                                 }
-                                if reasons.is_empty() {
-                                    return None;
-                                }
-                                if user.created <= other_user.created {
-                                    return Some((other_user, reasons));
+                                reasons.remove(&models::purge::PurgeReason::StaleInvitation);
+                                match reqwest.resend_jira_invitation(robot, user).await {
+                                    Ok(true) => {
+                                        if let Err(error) = mongodb.add_purge_invitation(&models::purge::PurgeInvitation::new(robot, user, now)).await {
+                                            log::error!("Robot {:?} failed to record invitation of user {:?} ({})", robot.data.name, user.display_name, error);
+                                        }
+                                        log::info!("Robot {:?} has re-sent the invitation of user {:?}", robot.data.name, user.display_name);
+                                    },
+                                    Ok(false) => {
+                                        log::error!("Robot {:?} failed to re-send the invitation of user {:?}", robot.data.name, user.display_name);
+                                    },
+                                    Err(error) => {
+                                        log::error!("Robot {:?} failed to re-send the invitation of user {:?} ({})", robot.data.name, user.display_name, error);
+                                    }
                                 }
-                                return Some((user, reasons));
-                            });
-                    })
-                    .collect::<Vec<_>>();
-
-                // Get all inactive jira users
-                let inactive_users = users
-                    .par_iter()
-                    .flat_map(|user| {
-                        let mut reasons: std::collections::HashSet<models::purge::PurgeReason> =
-                            std::collections::HashSet::new();
-
-                        // Check active status
-                        if robot.config.scheduler.check_active_status && !user.active {
-                            reasons.insert(models::purge::PurgeReason::ActiveStatus);
-                        }
-
-                        // Check presence
-                        if robot.config.scheduler.last_active > 0
-                            && user.get_available_presence()
-                                <= now - chrono::Duration::days(robot.config.scheduler.last_active)
-                        {
-                            reasons.insert(models::purge::PurgeReason::LastActive);
-                        }
-
-                        if reasons.is_empty() {
-                            return None;
+                            }
+                        },
+                        Err(error) => {
+                            // Without the history we can't tell who was already re-invited, so stale invitations wait for the next run
+                            log::error!("Robot {:?} failed to retrieve re-sent invitations ({})", robot.data.name, error);
+                            for (_user, reasons) in unique_filtered_users.iter_mut() {
+                                reasons.remove(&models::purge::PurgeReason::StaleInvitation);
+                            }
                         }
+                    }
+                }
+                unique_filtered_users.retain(|(_user, reasons)| !reasons.is_empty());
 
-                        return Some((user, reasons));
-                    })
-                    .collect::<Vec<_>>();
-
-                let mut filtered_users: Vec<(
-                    &models::jira::User,
-                    std::collections::HashSet<models::purge::PurgeReason>,
-                )> = Vec::new();
-                filtered_users.extend(duplicate_users);
-                filtered_users.extend(inactive_users);
+                // Users who asked to keep their account aren't queued again until their exemption ends
+                match mongodb.get_purge_exemptions(&robot.data.id).await {
+                    Ok(exemptions) => {
+                        unique_filtered_users.retain(|(user, _reasons)| {
+                            return !exemptions.iter().any(|exemption| exemption.user.id == user.id && exemption.until > now);
+                        });
+                    },
+                    Err(error) => {
+                        log::error!("Robot {:?} failed to retrieve exemptions ({})", robot.data.name, error);
+                        unique_filtered_users.clear();
+                    }
+                }
 
-                // Combine or remove duplicate users data since we seperate the loop between duplicate and inactivity
-                let unique_filtered_users = filtered_users
-                    .into_iter()
-                    .fold(
-                        std::collections::HashMap::<
-                            String,
-                            (
-                                &models::jira::User,
-                                std::collections::HashSet<models::purge::PurgeReason>,
-                            ),
-                        >::new(),
-                        |mut data, (user, reason)| {
-                            data.entry(user.id.clone())
-                                .and_modify(|(_existing_user, existing_reason)| {
-                                    // If a user with the same ID already exists, append their reason
-                                    existing_reason.extend(reason.clone());
-                                })
-                                .or_insert((user, reason));
-                            return data;
-                        },
-                    )
-                    .into_iter()
-                    .map(|(_, user)| user)
-                    .collect::<Vec<_>>();
-                
                 // Add users to purge users queue
                 for (user, reasons) in unique_filtered_users {
-                    let purge_data = models::purge::PurgeData::new(
+                    let mut purge_data = models::purge::PurgeData::new(
                         robot,
                         user,
                         reasons.into_iter().collect(),
                         now + chrono::Duration::days(7),
                     );
 
+                    // System accounts are never purged, admins only when the robot opted in or someone approves it
+                    let protection = if user.system {
+                        add_purge_decision(mongodb, robot, &purge_data, models::purge::PurgeProtection::SystemAccount, now).await;
+                        continue;
+                    } else if user.is_privileged() && robot.config.scheduler.purge_admins {
+                        Some(models::purge::PurgeProtection::OptedIn)
+                    } else if user.is_privileged() {
+                        purge_data.protected = true;
+                        Some(models::purge::PurgeProtection::AwaitingApproval)
+                    } else {
+                        None
+                    };
+
                     match mongodb.add_purge_user(&purge_data).await {
                         Ok(result) => {
                             if result.upserted_id.is_some() {
@@ -196,6 +1447,9 @@ async fn tick(
                                     robot.data.name,
                                     purge_data.reasons,
                                 );
+                                if let Some(protection) = protection {
+                                    add_purge_decision(mongodb, robot, &purge_data, protection, now).await;
+                                }
                             }
                         },
                         Err(error) => {
@@ -205,6 +1459,14 @@ async fn tick(
                 }
             }
 
+            let snapshot = models::snapshot::Snapshot::new(robot, users.len(), diff, scoring.unwrap_or_default(), now);
+            save_snapshot(mongodb, robot, &users, &previous, duplicates, snapshot, previous_version).await;
+
+            if *shutdown.borrow() {
+                interrupt_robot(mongodb, robot, last_run, interrupted).await;
+                return;
+            }
+
             if let Ok(mut purge_data) = mongodb.get_purge_users().await {
                 let purge_data = purge_data
                 .par_iter_mut()
@@ -213,37 +1475,73 @@ async fn tick(
                 })
                 .collect::<Vec<_>>();
 
+                // Decide for every queued user whether their purge still holds
+                let mut queue = Vec::with_capacity(purge_data.len());
                 for data in purge_data {
-                    if let Some(user) = users.iter().find(|user|user.id == data.user.id) {
-                        let mut remove = false;
-                        for reason in &data.reasons {
-                            match *reason {
-                                models::purge::PurgeReason::ActiveStatus => {
-                                    if !user.active {
-                                        remove = true;
-                                    }
-                                },
-                                models::purge::PurgeReason::DuplicateEmail => {
-                                    if user.email == data.user.email {
-                                        remove = true;
-                                    }
-                                },
-                                models::purge::PurgeReason::DuplicateName => {
-                                    if user.display_name == data.user.display_name {
-                                        remove = true;
-                                    }
-                                },
-                                models::purge::PurgeReason::LastActive => {
-                                    if user.get_available_presence() <= data.user.presence {
-                                        remove = true;
-                                    }
-                                },
-                            }
-                            if remove == true {
-                                break;
+                    let user = users.iter().find(|user| user.id == data.user.id);
+                    let remove = match user {
+                        Some(user) => is_purge_valid(source.as_ref(), robot, user, data, &role_holders, &memberships, now).await,
+                        None => false,
+                    };
+                    queue.push((data, user, remove));
+                }
+
+                // Stop before removing more users than the robot allows, an API glitch can make everyone look inactive
+                let removals = queue
+                    .iter()
+                    .filter(|(data, user, remove)| user.is_some() && *remove && data.should_remove_user(now) && !data.is_awaiting_approval())
+                    .count();
+                let acknowledged = robot.data.breaker.as_ref().is_some_and(|breaker| breaker.acknowledged.is_some());
+                if !acknowledged && robot.exceeds_removal_limits(removals, users.len()) {
+                    pause_robot(reqwest, mongodb, robot, notification_config, removals, users.len(), now).await;
+                    return;
+                }
+                if acknowledged {
+                    // An acknowledgement only covers the run it resumed
+                    robot.data.breaker = None;
+                    if let Err(error) = mongodb.set_robot_status(robot).await {
+                        log::error!("Robot {:?} failed to clear its pause ({})", robot.data.name, error);
+                    }
+                }
+
+                // The manager mapping is read once per run, users without a manager go to the contacts when it can't be
+                let managers = match load_managers(notification_config, &robot.config.scheduler.escalation) {
+                    Ok(managers) => managers,
+                    Err(error) => {
+                        log::error!("Robot {:?} failed to load its managers ({})", robot.data.name, error);
+                        None
+                    }
+                };
+
+                for (data, user, remove) in queue {
+                    // A removal in progress runs to the end, the next user waits for the next start
+                    if *shutdown.borrow() {
+                        interrupt_robot(mongodb, robot, last_run, interrupted).await;
+                        return;
+                    }
+
+                    // A removal that was started is seen through, even when a partial removal makes the user look valid again
+                    if data.is_attempted() {
+                        match user {
+                            Some(user) => {
+                                if data.state == models::purge::PurgeState::Removed {
+                                    let log = models::purge::PurgeLog::new(robot, user, data, data.action.clone().unwrap_or_default(), now);
+                                    finish_purge(mongodb, robot, data, log).await;
+                                }
+                                else if data.attempts < scheduler_config.purge_attempts {
+                                    remove_user(context, source.as_ref(), robot, user, data, now).await;
+                                }
+                            },
+                            None => {
+                                // The user is gone, so the interrupted or failed removal went through after all
+                                let log = models::purge::PurgeLog::from_purge(robot, data, now);
+                                finish_purge(mongodb, robot, data, log).await;
                             }
                         }
+                        continue;
+                    }
 
+                    if let Some(user) = user {
                         if !remove { // If there isn't any reason to have this user in purging queue anymore, remove it
                             if data.should_remove_user(now) {
                                 match mongodb.delete_purge_user(data).await {
@@ -260,48 +1558,27 @@ async fn tick(
                             continue;
                         }
 
-                        if data.should_remove_user(now) {
-                            // Log removed user
-                            // Remove purge_data from purge_users database
-                            // Remove user from jira
-                            match mongodb.add_purge_log(&models::purge::PurgeLog::new(robot, user, data.reasons.clone(), now)).await {
-                                Ok(_result) => match mongodb.delete_purge_user(data).await {
-                                    Ok(result) => {
-                                        if result.deleted_count > 0 {
-                                            match reqwest.remove_user_from_jira(robot, data).await {
-                                               Ok(response) => {
-                                                    if response.status() == reqwest::StatusCode::NO_CONTENT {
-                                                        log::info!("Robot {:?} has remove user {:?} from organization because of the following reason(s): {:?}", robot.data.name, user.display_name, data.reasons);
-                                                    }
-                                                    else {
-                                                        log::error!("Robot {:?} failed to remove user {:?} from organization ({:?})", robot.data.name, data.user.display_name, response);
-                                                    }
-                                                },
-                                                Err(error) => {
-                                                    log::error!("Robot {:?} failed to remove user {:?} from organization ({:?})", robot.data.name, data.user.display_name, error);
-                                                }
-                                            }
-                                        }
-                                        else {
-                                            log::warn!("Robot {:?} failed to find user {:?} from purging queue", robot.data.name, data.user.display_name);
-                                        }
-                                    },
-                                    Err(error) => {
-                                        log::error!("Robot {:?} failed to remove user {:?} from purging queue ({:?})", robot.data.name, data.user.display_name, error);
-                                    }
-                                },
-                                Err(error) => {
-                                    log::error!("Robot {:?} failed to log user {:?} removal ({:?})", robot.data.name, data.user.display_name, error);
-                                }
-                            }
+                        if data.should_remove_user(now) && data.is_awaiting_approval() {
+                            log::warn!("Robot {:?} is waiting for approval to purge privileged user {:?}", robot.data.name, user.display_name);
                         }
-                        else if data.should_email_user(now, 3) {
+                        else if data.should_remove_user(now) {
+                            remove_user(context, source.as_ref(), robot, user, data, now).await;
+                        }
+                        // Users without an email are neither warned nor escalated, they're removed when their time comes
+                        else if data.should_email_user(now, 3) && !user.email.is_empty() {
+                            // Reminders the user let pass without keeping their account go up the chain
+                            if data.should_escalate(&robot.config.scheduler.escalation) {
+                                escalate_user(mongodb, notification_config, robot, user, data, &managers, now).await;
+                            }
+
                             // Patch purge alert
                             // Email user
                             data.alert = Some(now);
+                            data.reminders += 1;
+                            data.state = models::purge::PurgeState::Warned;
                             if let Ok(result) = mongodb.patch_purge_user(data).await {
                                 if result.modified_count > 0 {
-                                    if data.email_user(&notification_config.email, &notification_config.password, &robot.config.credential.platform_email) {
+                                    if data.email_user(&notification_config.email, &notification_config.password, &robot.config.credential.platform_email, utils::token::get_keep_link(notification_config, data)) {
                                         log::info!("Robot {:?} has notified user {:?} through {:?}", robot.data.name, user.display_name, user.email);
                                     }
                                 }
@@ -333,14 +1610,80 @@ async fn tick(
         })
         .collect::<Vec<_>>();
 
-    // Wait for all the task to finish
-    futures::future::join_all(futures).await;
+    // Run the robots on a bounded number of workers, each holding its robot's lock and finishing on its own
+    let futures = futures
+        .into_iter()
+        .zip(locks)
+        .map(|(future, (lock, name))| {
+            let future = async move {
+                // A robot stuck on a slow site gives its worker back, it's due again on its next run
+                if tokio::time::timeout(
+                    scheduler_config.robot_timeout.to_std().unwrap_or_default(),
+                    future,
+                )
+                .await
+                .is_err()
+                {
+                    log::error!(
+                        "Robot {:?} timed out after {} seconds",
+                        name,
+                        scheduler_config.robot_timeout.num_seconds()
+                    );
+                }
+            };
+            async move {
+                hold_lock(mongodb, scheduler_config, lock.clone(), future).await;
+                running.lock().unwrap().remove(&lock);
+            }
+        });
 
-    return Ok(());
+    // Wait for all the robots to finish, or give up on them at the shutdown deadline
+    tokio::select! {
+        _ = futures::StreamExt::collect::<Vec<_>>(futures::StreamExt::buffer_unordered(
+            futures::stream::iter(futures),
+            std::cmp::max(scheduler_config.workers, 1),
+        )) => {},
+        _ = wait_for_deadline(shutdown.clone(), scheduler_config.shutdown_deadline) => {
+            log::error!("Shutdown deadline passed with robots still running");
+        }
+    }
+
+    // Robots cut off at the deadline are due again on the next start, their leases are released for the other instances
+    let running = running_robots.into_inner().unwrap();
+    for (robot, last_run) in active_robots.iter_mut().zip(last_runs) {
+        let lock = get_lock_name(robot);
+        if running.contains(&lock) {
+            interrupt_robot(mongodb, robot, last_run, interrupted).await;
+            if let Err(error) = mongodb
+                .release_lock(&lock, &scheduler_config.instance_id)
+                .await
+            {
+                log::error!("Failed to release lock {:?} ({})", lock, error);
+            }
+        }
+    }
+    let interrupted = interrupted_robots.into_inner().unwrap();
+    if !interrupted.is_empty() {
+        log::warn!(
+            "Shutdown interrupted robot(s) {:?}, they run again on the next start",
+            interrupted
+        );
+    }
+
+    // The earliest robot with a cron of its own decides when to wake up before the global schedule.
+    // Robots locked by another instance keep a stale last run and look overdue, so they're checked again a lease fraction later.
+    let earliest = chrono::Utc::now() + scheduler_config.lease / 3;
+    return Ok(robots
+        .iter()
+        .filter(|robot| robot.data.active)
+        .filter_map(|robot| robot.get_next_run())
+        .min()
+        .map(|next_run| std::cmp::max(next_run, earliest)));
 }
 
 pub async fn run(
-    mut exit_receiver: tokio::sync::mpsc::Receiver<()>,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+    wake: std::sync::Arc<tokio::sync::Notify>,
     scheduler_config: configs::scheduler::Config,
     notification_config: configs::notification::Config,
     reqwest: clients::reqwest::Client,
@@ -349,39 +1692,780 @@ pub async fn run(
 ) {
     // Initialize a variable to track the time of the last run.
     let mut last_run: Option<chrono::DateTime<chrono::Utc>> = None;
+    let mut next_robot_run: Option<chrono::DateTime<chrono::Utc>> = None;
+    let mut changed = true;
+    let mut was_leader = false;
+
+    // Finish or flag the removals a previous run left halfway.
+    if let Err(error) = reconcile(&mongodb, &rusoto, &scheduler_config).await {
+        log::error!("Failed to reconcile interrupted removals ({})", error);
+    }
+
+    let context = RunContext {
+        scheduler_config: &scheduler_config,
+        notification_config: &notification_config,
+        reqwest: &reqwest,
+        mongodb: &mongodb,
+        rusoto: &rusoto,
+    };
+
     loop {
+        // A shutdown requested during a run stops the scheduler right after it.
+        if *shutdown.borrow() {
+            log::info!("shutting down scheduler");
+            break;
+        }
+
         // Get the current time.
         let now = chrono::Utc::now();
 
-        // If this is the first run or the schedule has elapsed since the last run, run `tick`.
-        if last_run.is_none()
+        // If this is the first run or the schedule has elapsed since the last run, every robot without a cron of its own is due.
+        let global = last_run.is_none()
             || scheduler_config
                 .schedule
                 .after(&last_run.unwrap())
                 .next()
                 .unwrap()
-                <= now
+                <= now;
+
+        // In leader election mode only the leader runs the robots, the others stand by to take over.
+        let leader = !scheduler_config.leader_election
+            || match mongodb
+                .acquire_lock(
+                    &"scheduler".to_string(),
+                    &scheduler_config.instance_id,
+                    chrono::Utc::now(),
+                    scheduler_config.lease,
+                )
+                .await
+            {
+                Ok(acquired) => acquired,
+                Err(error) => {
+                    log::error!("Failed to elect a leader ({})", error);
+                    false
+                }
+            };
+
+        if leader && !was_leader && scheduler_config.leader_election {
+            log::info!(
+                "instance {:?} is now the scheduler leader",
+                scheduler_config.instance_id
+            );
+            changed = true;
+        }
+        was_leader = leader;
+
+        // Run the robots that are due, and learn when the next one is.
+        if leader
+            && (global
+                || changed
+                || next_robot_run.is_some_and(|next_robot_run| next_robot_run <= now))
         {
-            if let Err(error) = tick(now, &reqwest, &mongodb, &rusoto, &notification_config).await {
-                log::error!("{}", error)
-            }
+            let future = tick(now, global, &context, &shutdown);
+
+            // The leader keeps renewing its lease while the robots run, a tick can outlast it
+            let result = if scheduler_config.leader_election {
+                keep_lock(
+                    &mongodb,
+                    &scheduler_config,
+                    &"scheduler".to_string(),
+                    future,
+                )
+                .await
+            } else {
+                Some(future.await)
+            };
+            next_robot_run = match result {
+                Some(Ok(next_robot_run)) => next_robot_run,
+                Some(Err(error)) => {
+                    log::error!("{}", error);
+                    None
+                }
+                None => {
+                    log::warn!(
+                        "instance {:?} is no longer the scheduler leader",
+                        scheduler_config.instance_id
+                    );
+                    was_leader = false;
+                    None
+                }
+            };
+            changed = false;
+        }
+        if global {
             last_run = Some(now);
         }
 
-        // Calculate the amount of time to sleep until the next scheduled run.
-        let sleep_duration: chrono::Duration =
-            match scheduler_config.schedule.after(&last_run.unwrap()).next() {
-                Some(next_run) => next_run - now,
-                None => chrono::Duration::from_std(std::time::Duration::from_secs(1)).unwrap(),
-            };
+        // Calculate the amount of time to sleep until the next scheduled run or the next robot is due.
+        let next_run = scheduler_config
+            .schedule
+            .after(&last_run.unwrap())
+            .next()
+            .into_iter()
+            .chain(next_robot_run)
+            .min();
+        let mut sleep_duration: chrono::Duration = match next_run {
+            Some(next_run) => next_run - chrono::Utc::now(),
+            None => chrono::Duration::from_std(std::time::Duration::from_secs(1)).unwrap(),
+        };
 
-        // Wait until it's time for the next scheduled run or until the exit receiver receives a message.
+        // The leader renews its lease while idle, the others check whether it's gone at least once per lease.
+        if scheduler_config.leader_election {
+            sleep_duration = std::cmp::min(sleep_duration, scheduler_config.lease / 3);
+        }
+
+        // Wait until it's time for the next run, a robot changed or a shutdown is requested.
         tokio::select! {
-            _ = actix_rt::time::sleep(sleep_duration.to_std().unwrap()) => {},
-            _ = exit_receiver.recv() => {
+            _ = actix_rt::time::sleep(sleep_duration.to_std().unwrap_or_default()) => {},
+            _ = wake.notified() => {
+                log::info!("robots changed, rescheduling");
+                changed = true;
+            },
+            _ = shutdown.changed() => {
                 log::info!("shutting down idle scheduler");
                 break;
             }
         }
     }
+
+    // Hand leadership over right away instead of letting the lease run out.
+    if was_leader && scheduler_config.leader_election {
+        if let Err(error) = mongodb
+            .release_lock(&"scheduler".to_string(), &scheduler_config.instance_id)
+            .await
+        {
+            log::error!("Failed to step down as the scheduler leader ({})", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    struct Context {
+        mock: utils::mock::Server,
+        reqwest: clients::reqwest::Client,
+        mongodb: clients::mongodb::Client,
+        rusoto: clients::rusoto::Client,
+        scheduler_config: configs::scheduler::Config,
+        notification_config: configs::notification::Config,
+        shutdown: tokio::sync::watch::Sender<bool>,
+    }
+
+    impl Context {
+        fn get_run_context(&self) -> super::RunContext<'_> {
+            return super::RunContext {
+                scheduler_config: &self.scheduler_config,
+                notification_config: &self.notification_config,
+                reqwest: &self.reqwest,
+                mongodb: &self.mongodb,
+                rusoto: &self.rusoto,
+            };
+        }
+    }
+
+    async fn setup() -> Context {
+        // These tests need a disposable MongoDB, e.g. `docker run -p 27017:27017 mongo`, and are ignored unless asked for
+        let uri = std::env::var("TEST_MONGODB_URI")
+            .expect("TEST_MONGODB_URI must point to a disposable MongoDB");
+
+        let mock = utils::mock::Server::start().await;
+        let reqwest = clients::reqwest::Client::new(configs::reqwest::Config {
+            organization_id: "organization".to_string(),
+            timeout: 5,
+            retries: 0,
+            backoff: 0,
+            rate: 1000.0,
+            burst: 1000.0,
+            site_url: mock.url.clone(),
+            admin_url: mock.url.clone(),
+            api_url: mock.url.clone(),
+            bitbucket_url: mock.url.clone(),
+        });
+        let mongodb = clients::mongodb::Client::new(configs::mongodb::Config {
+            username: String::new(),
+            password: String::new(),
+            uri: Some(uri),
+            database: format!("robots_test_{}", mongodb::bson::oid::ObjectId::new()),
+        })
+        .await
+        .unwrap();
+        let rusoto = clients::rusoto::Client::new(configs::rusoto::Config {
+            key: "key".to_string(),
+            secret: "secret".to_string(),
+            endpoint: mock.url.clone(),
+        })
+        .unwrap();
+
+        return Context {
+            mock,
+            reqwest,
+            mongodb,
+            rusoto,
+            scheduler_config: configs::scheduler::Config {
+                schedule: "0 0 0 * * *".parse().unwrap(),
+                instance_id: "test".to_string(),
+                lease: chrono::Duration::seconds(60),
+                leader_election: false,
+                purge_attempts: 3,
+                shutdown_deadline: chrono::Duration::seconds(5),
+                workers: 2,
+                robot_timeout: chrono::Duration::seconds(60),
+            },
+            notification_config: configs::notification::Config {
+                email: "robot@example.com".to_string(),
+                password: String::new(),
+                webhook: None,
+                public_url: None,
+                keep_secret: None,
+                keep_exemption: chrono::Duration::days(90),
+                managers_dir: None,
+            },
+            shutdown: tokio::sync::watch::channel(false).0,
+        };
+    }
+
+    async fn add_robot(context: &Context, cloud_session_token: &str) -> models::robot::Robot {
+        let mut robot = models::robot::Robot::default();
+        robot.data.name = "test".to_string();
+        robot.data.active = true;
+        robot.config.credential.platform_email = "owner@example.com".to_string();
+        robot.config.credential.platform_api_key = "key".to_string();
+        robot.config.credential.cloud_session_token = cloud_session_token.to_string();
+        robot.config.scheduler.schedule = 30;
+        robot.config.scheduler.last_active = 90;
+        robot.config.scheduler.check_active_status = true;
+
+        context.mongodb.add_robot(&mut robot).await.unwrap();
+        context.rusoto.add_robot(&robot).await.unwrap();
+        return robot;
+    }
+
+    async fn get_queue(context: &Context) -> Vec<String> {
+        let mut queue = context
+            .mongodb
+            .get_purge_users()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|data| data.user.id)
+            .collect::<Vec<_>>();
+        queue.sort();
+        return queue;
+    }
+
+    async fn teardown(context: Context) {
+        context.mongodb.drop_database().await.unwrap();
+        context.mock.stop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_queues_inactive_users() {
+        let context = setup().await;
+        add_robot(&context, "valid").await;
+
+        let now = chrono::Utc::now();
+        super::tick(
+            now,
+            true,
+            &context.get_run_context(),
+            &context.shutdown.subscribe(),
+        )
+        .await
+        .unwrap();
+
+        // Bob hasn't been seen for too long and Carol is inactive, the system account is never queued
+        assert_eq!(get_queue(&context).await, vec!["bob", "carol"]);
+        for data in context.mongodb.get_purge_users().await.unwrap() {
+            assert_eq!(data.time, now + chrono::Duration::days(7));
+        }
+        assert!(context.mongodb.get_purge_log().await.unwrap().is_empty());
+        assert!(context.mock.get_deleted().is_empty());
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_removes_users_after_grace_period() {
+        let context = setup().await;
+        add_robot(&context, "valid").await;
+
+        let now = chrono::Utc::now();
+        for now in [now, now + chrono::Duration::days(8)] {
+            super::tick(
+                now,
+                true,
+                &context.get_run_context(),
+                &context.shutdown.subscribe(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut deleted = context.mock.get_deleted();
+        deleted.sort();
+        assert_eq!(deleted, vec!["bob", "carol"]);
+        assert!(get_queue(&context).await.is_empty());
+
+        let mut logs = context.mongodb.get_purge_log().await.unwrap();
+        logs.sort_by(|a, b| a.user.id.cmp(&b.user.id));
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].user.id, "bob");
+        assert_eq!(
+            logs[0].reasons,
+            vec![models::purge::PurgeReason::LastActive]
+        );
+        assert_eq!(logs[1].user.id, "carol");
+        assert_eq!(
+            logs[1].reasons,
+            vec![models::purge::PurgeReason::ActiveStatus]
+        );
+        for log in &logs {
+            assert_eq!(log.action, models::purge::PurgeAction::Delete);
+        }
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_skips_users_who_kept_their_account() {
+        let context = setup().await;
+        let robot = add_robot(&context, "valid").await;
+
+        let now = chrono::Utc::now();
+        let shutdown = context.shutdown.subscribe();
+        let run_context = context.get_run_context();
+        let tick = |now| {
+            return super::tick(now, true, &run_context, &shutdown);
+        };
+        tick(now).await.unwrap();
+
+        let data = context
+            .mongodb
+            .get_purge_users()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|data| data.user.id == "bob")
+            .unwrap();
+        let secret = "secret".to_string();
+        let token = utils::token::sign(&secret, data.id.as_ref().unwrap(), data.time);
+        assert!(utils::token::verify("other", &token, now).is_err());
+        assert!(
+            utils::token::verify(&secret, &token, data.time + chrono::Duration::seconds(1))
+                .is_err()
+        );
+        let purge_id = models::purge::PurgeIdentifier {
+            unique: Some(utils::token::verify(&secret, &token, now).unwrap()),
+        };
+
+        let data = context
+            .mongodb
+            .get_purge_user(&purge_id)
+            .await
+            .unwrap()
+            .unwrap();
+        context
+            .mongodb
+            .add_purge_exemption(&models::purge::PurgeExemption::new(
+                &data,
+                now,
+                now + chrono::Duration::days(30),
+            ))
+            .await
+            .unwrap();
+        assert!(context
+            .mongodb
+            .keep_purge_user(&purge_id)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(get_queue(&context).await, vec!["carol"]);
+
+        // Bob is left alone while exempt and queued again once the exemption ends
+        tick(now + chrono::Duration::days(1)).await.unwrap();
+        assert_eq!(get_queue(&context).await, vec!["carol"]);
+        assert!(context
+            .mongodb
+            .keep_purge_user(&purge_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            context
+                .mongodb
+                .get_purge_exemptions(&robot.data.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        tick(now + chrono::Duration::days(31)).await.unwrap();
+        assert!(get_queue(&context).await.contains(&"bob".to_string()));
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_keeps_users_before_grace_period() {
+        let context = setup().await;
+        add_robot(&context, "valid").await;
+
+        let now = chrono::Utc::now();
+        for now in [now, now + chrono::Duration::days(6)] {
+            super::tick(
+                now,
+                true,
+                &context.get_run_context(),
+                &context.shutdown.subscribe(),
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(get_queue(&context).await, vec!["bob", "carol"]);
+        assert!(context.mongodb.get_purge_log().await.unwrap().is_empty());
+        assert!(context.mock.get_deleted().is_empty());
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_expires_rejected_credentials() {
+        let context = setup().await;
+        let robot = add_robot(&context, "expired").await;
+
+        super::tick(
+            chrono::Utc::now(),
+            true,
+            &context.get_run_context(),
+            &context.shutdown.subscribe(),
+        )
+        .await
+        .unwrap();
+
+        let data = context
+            .mongodb
+            .get_robot(&robot.data.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            data.credential_status.map(|status| status.state),
+            Some(models::robot::RobotCredentialState::Expired)
+        );
+        assert!(get_queue(&context).await.is_empty());
+        assert!(context.mock.get_deleted().is_empty());
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_skips_robots_locked_by_another_instance() {
+        let context = setup().await;
+        let robot = add_robot(&context, "valid").await;
+        let lock = super::get_lock_name(&robot);
+        assert!(context
+            .mongodb
+            .acquire_lock(
+                &lock,
+                &"other".to_string(),
+                chrono::Utc::now(),
+                chrono::Duration::seconds(60)
+            )
+            .await
+            .unwrap());
+
+        super::tick(
+            chrono::Utc::now(),
+            true,
+            &context.get_run_context(),
+            &context.shutdown.subscribe(),
+        )
+        .await
+        .unwrap();
+        assert!(get_queue(&context).await.is_empty());
+
+        // Once the other instance lets go, the robot runs again
+        context
+            .mongodb
+            .release_lock(&lock, &"other".to_string())
+            .await
+            .unwrap();
+        super::tick(
+            chrono::Utc::now(),
+            true,
+            &context.get_run_context(),
+            &context.shutdown.subscribe(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(get_queue(&context).await, vec!["bob", "carol"]);
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn reconcile_finishes_interrupted_removals() {
+        let context = setup().await;
+        add_robot(&context, "valid").await;
+
+        let now = chrono::Utc::now();
+        super::tick(
+            now,
+            true,
+            &context.get_run_context(),
+            &context.shutdown.subscribe(),
+        )
+        .await
+        .unwrap();
+
+        // Bob was removed but not yet logged, Carol's removal was cut off before Atlassian answered
+        for mut data in context.mongodb.get_purge_users().await.unwrap() {
+            data.attempts = 1;
+            data.action = Some(models::purge::PurgeAction::Delete);
+            data.state = match data.user.id.as_str() {
+                "bob" => models::purge::PurgeState::Removed,
+                _ => models::purge::PurgeState::Removing,
+            };
+            context.mongodb.patch_purge_user(&data).await.unwrap();
+        }
+
+        super::reconcile(&context.mongodb, &context.rusoto, &context.scheduler_config)
+            .await
+            .unwrap();
+        assert_eq!(get_queue(&context).await, vec!["carol"]);
+        let logs = context.mongodb.get_purge_log().await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].user.id, "bob");
+        let data = context.mongodb.get_purge_users().await.unwrap();
+        assert_eq!(data[0].state, models::purge::PurgeState::Failed);
+        assert!(context.mock.get_deleted().is_empty());
+
+        // The next run retries Carol's removal
+        super::tick(
+            now + chrono::Duration::days(8),
+            true,
+            &context.get_run_context(),
+            &context.shutdown.subscribe(),
+        )
+        .await
+        .unwrap();
+        assert!(get_queue(&context).await.is_empty());
+        assert_eq!(context.mock.get_deleted(), vec!["carol"]);
+        assert_eq!(context.mongodb.get_purge_log().await.unwrap().len(), 2);
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_stops_for_shutdown() {
+        let context = setup().await;
+        let robot = add_robot(&context, "valid").await;
+
+        context.shutdown.send_replace(true);
+        super::tick(
+            chrono::Utc::now(),
+            true,
+            &context.get_run_context(),
+            &context.shutdown.subscribe(),
+        )
+        .await
+        .unwrap();
+
+        // The robot didn't start, so it's due again on the next start
+        assert!(get_queue(&context).await.is_empty());
+        let data = context
+            .mongodb
+            .get_robot(&robot.data.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.last_run, None);
+
+        teardown(context).await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a MongoDB at TEST_MONGODB_URI"]
+    async fn tick_saves_user_snapshot() {
+        let context = setup().await;
+        let robot = add_robot(&context, "valid").await;
+
+        let now = chrono::Utc::now();
+        for now in [now, now + chrono::Duration::days(1)] {
+            super::tick(
+                now,
+                true,
+                &context.get_run_context(),
+                &context.shutdown.subscribe(),
+            )
+            .await
+            .unwrap();
+        }
+
+        // The second run finds the same users as the first
+        let snapshot = context
+            .mongodb
+            .get_snapshot(&robot.data.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.total, 4);
+        assert!(snapshot.added.is_empty());
+        assert!(snapshot.changed.is_empty());
+        assert!(snapshot.removed.is_empty());
+        assert_eq!(
+            context
+                .mongodb
+                .get_snapshot_users(&snapshot)
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+
+        teardown(context).await;
+    }
+
+    #[test]
+    fn score_duplicates_only_rescores_changed_users() {
+        let mut robot = models::robot::Robot::default();
+        robot.data.id.unique = Some(mongodb::bson::oid::ObjectId::new());
+        robot.config.scheduler.check_double_name = true;
+        robot.config.scheduler.double_name_threshold = 90.0;
+
+        let now = chrono::Utc::now();
+        let mut users = ["Alice Smith", "Alice Smith", "Bob Jones"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| models::jira::User {
+                id: ["a", "b", "c"][i].to_string(),
+                display_name: name.to_string(),
+                created: Some(now + chrono::Duration::days(i as i64)),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let duplicates =
+            super::score_duplicates(&robot, &users, &std::collections::HashMap::new(), true);
+        assert_eq!(
+            duplicates["b"].keys().collect::<Vec<_>>(),
+            vec![&"a".to_string()]
+        );
+
+        // A marker on an unchanged pair shows it was kept rather than scored again
+        let mut previous = users
+            .iter()
+            .map(|user| {
+                let mut snapshot = models::snapshot::SnapshotUser::new(&robot, user, now);
+                snapshot.duplicates = duplicates.get(&user.id).cloned().unwrap_or_default();
+                snapshot.scored = Some(super::get_identity(user));
+                return (user.id.clone(), snapshot);
+            })
+            .collect::<std::collections::HashMap<_, _>>();
+        previous
+            .get_mut("b")
+            .unwrap()
+            .duplicates
+            .get_mut("a")
+            .unwrap()
+            .insert(models::purge::PurgeReason::DuplicateEmail);
+
+        users[2].display_name = "Alice Smith".to_string();
+        let duplicates = super::score_duplicates(&robot, &users, &previous, false);
+        assert!(duplicates["b"]["a"].contains(&models::purge::PurgeReason::DuplicateEmail));
+        assert_eq!(
+            duplicates["c"].keys().collect::<Vec<_>>(),
+            vec![&"a".to_string(), &"b".to_string()]
+        );
+    }
+
+    #[test]
+    fn escalation_prefers_the_manager_over_contacts() {
+        let user = models::jira::User {
+            id: "bob".to_string(),
+            email: "Bob@example.com".to_string(),
+            department: Some("carol@example.com".to_string()),
+            ..Default::default()
+        };
+        let mut escalation = models::robot::RobotEscalation {
+            reminders: 2,
+            managers: None,
+            contacts: vec!["owner@example.com".to_string()],
+        };
+        assert_eq!(
+            super::get_escalation_recipients(&escalation, &None, &user),
+            vec!["owner@example.com"]
+        );
+
+        escalation.managers = Some(models::robot::RobotManagerSource::Attribute {
+            field: models::policy::PolicyTextField::Department,
+        });
+        assert_eq!(
+            super::get_escalation_recipients(&escalation, &None, &user),
+            vec!["carol@example.com"]
+        );
+
+        let directory = std::env::temp_dir().join(format!("managers-{}", rand::random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::write(
+            directory.join("managers.yaml"),
+            "bob@example.com: dave@example.com\nalice: erin@example.com\n",
+        )
+        .unwrap();
+        let mut notification_config = configs::notification::Config {
+            email: String::new(),
+            password: String::new(),
+            webhook: None,
+            public_url: None,
+            keep_secret: None,
+            keep_exemption: chrono::Duration::days(90),
+            managers_dir: None,
+        };
+        escalation.managers = Some(models::robot::RobotManagerSource::File {
+            path: "managers.yaml".to_string(),
+        });
+        let escalation_config = Some(escalation.clone());
+        assert!(super::load_managers(&notification_config, &escalation_config).is_err());
+
+        notification_config.managers_dir = Some(directory.to_string_lossy().to_string());
+        let managers = super::load_managers(&notification_config, &escalation_config).unwrap();
+        assert_eq!(
+            super::get_escalation_recipients(&escalation, &managers, &user),
+            vec!["dave@example.com"]
+        );
+
+        // Paths leading out of the directory are refused
+        for path in ["../managers.yaml", "/etc/passwd", "sub/managers.yaml"] {
+            escalation.managers = Some(models::robot::RobotManagerSource::File {
+                path: path.to_string(),
+            });
+            assert!(super::load_managers(&notification_config, &Some(escalation.clone())).is_err());
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        // Only once, after the user let the configured number of reminders pass
+        let mut data = models::purge::PurgeData {
+            reminders: 1,
+            ..Default::default()
+        };
+        assert!(!data.should_escalate(&Some(escalation.clone())));
+        data.reminders = 2;
+        assert!(data.should_escalate(&Some(escalation.clone())));
+        assert!(!data.should_escalate(&None));
+        data.escalations
+            .push(models::purge::PurgeEscalation::default());
+        assert!(!data.should_escalate(&Some(escalation)));
+    }
 }
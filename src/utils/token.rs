@@ -0,0 +1,112 @@
+use crate::*;
+use hmac::Mac;
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+fn get_signature(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload.as_bytes());
+    return mac;
+}
+
+// Tokens are "<purge id>.<expiry>" signed with the secret, so nothing has to be stored to check them
+pub fn sign(
+    secret: &str,
+    purge_id: &mongodb::bson::oid::ObjectId,
+    expires: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let payload = format!("{}.{}", purge_id.to_hex(), expires.timestamp());
+    let signature = get_signature(secret, &payload).finalize().into_bytes();
+    return format!(
+        "{}.{}",
+        payload,
+        base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+    );
+}
+
+pub fn verify(
+    secret: &str,
+    token: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<mongodb::bson::oid::ObjectId, String> {
+    let (payload, signature) = token
+        .rsplit_once('.')
+        .ok_or("Token is malformed".to_string())?;
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .map_err(|_error| "Token is malformed".to_string())?;
+    get_signature(secret, payload)
+        .verify_slice(&signature)
+        .map_err(|_error| "Token signature is invalid".to_string())?;
+
+    let (purge_id, expires) = payload
+        .split_once('.')
+        .ok_or("Token is malformed".to_string())?;
+    let expires = expires
+        .parse::<i64>()
+        .map_err(|_error| "Token is malformed".to_string())?;
+    if now.timestamp() > expires {
+        return Err("Token has expired".to_string());
+    }
+    return mongodb::bson::oid::ObjectId::parse_str(purge_id)
+        .map_err(|_error| "Token is malformed".to_string());
+}
+
+pub fn get_keep_link(
+    notification_config: &configs::notification::Config,
+    data: &models::purge::PurgeData,
+) -> Option<String> {
+    let public_url = notification_config.public_url.as_ref()?;
+    let secret = notification_config.keep_secret.as_ref()?;
+    // The link works until the user would have been removed
+    return Some(format!(
+        "{}/purge/keep?token={}",
+        public_url.trim_end_matches('/'),
+        sign(secret, data.id.as_ref()?, data.time)
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn verify_accepts_only_signed_unexpired_tokens() {
+        let secret = "secret".to_string();
+        let purge_id = mongodb::bson::oid::ObjectId::new();
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2023, 1, 1, 0, 0, 0).unwrap();
+        let expires = now + chrono::Duration::days(7);
+        let token = utils::token::sign(&secret, &purge_id, expires);
+
+        assert_eq!(utils::token::verify(&secret, &token, now), Ok(purge_id));
+        assert_eq!(utils::token::verify(&secret, &token, expires), Ok(purge_id));
+        assert_eq!(
+            utils::token::verify(&secret, &token, expires + chrono::Duration::seconds(1)),
+            Err("Token has expired".to_string())
+        );
+        assert_eq!(
+            utils::token::verify("other", &token, now),
+            Err("Token signature is invalid".to_string())
+        );
+
+        // Changing the purge or the expiry breaks the signature
+        let other = utils::token::sign(&secret, &mongodb::bson::oid::ObjectId::new(), expires);
+        let (_payload, signature) = other.rsplit_once('.').unwrap();
+        let (payload, _signature) = token.rsplit_once('.').unwrap();
+        assert!(utils::token::verify(&secret, &format!("{}.{}", payload, signature), now).is_err());
+        let forged = format!(
+            "{}.{}.{}",
+            purge_id.to_hex(),
+            (expires + chrono::Duration::days(1)).timestamp(),
+            token.rsplit_once('.').unwrap().1
+        );
+        assert_eq!(
+            utils::token::verify(&secret, &forged, now),
+            Err("Token signature is invalid".to_string())
+        );
+
+        assert_eq!(
+            utils::token::verify(&secret, "garbage", now),
+            Err("Token is malformed".to_string())
+        );
+    }
+}